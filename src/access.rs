@@ -0,0 +1,297 @@
+use crate::errors::MacgyverCmdErrors;
+use crate::Result;
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use k8s_openapi::api::authorization::v1::{
+    ResourceAttributes, SelfSubjectAccessReview, SelfSubjectAccessReviewSpec,
+};
+use kube::api::PostParams;
+use kube::config::Kubeconfig;
+use kube::{api::Api, Client};
+use regex::RegexBuilder;
+use serde::Serialize;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+/// Regex of the kubeconfig contexts where revealing secrets must be confirmed
+const PROTECTED_CONTEXTS_VAR: &str = "MACGYVER_CMD_PROTECTED_CONTEXTS";
+/// Path of the audit log, `~/.macgyver/audit.log` by default
+const AUDIT_LOG_VAR: &str = "MACGYVER_CMD_AUDIT_LOG";
+
+/// Asks the API server (SelfSubjectAccessReview) whether the current user may list the
+/// secrets of the namespace, before reading any of them
+pub async fn check_secret_access(client: Client, namespace: &str) -> Result<()> {
+    let review = SelfSubjectAccessReview {
+        spec: SelfSubjectAccessReviewSpec {
+            resource_attributes: Some(ResourceAttributes {
+                namespace: Some(namespace.to_string()),
+                verb: Some("list".to_string()),
+                resource: Some("secrets".to_string()),
+                ..ResourceAttributes::default()
+            }),
+            ..SelfSubjectAccessReviewSpec::default()
+        },
+        ..SelfSubjectAccessReview::default()
+    };
+    let status = Api::<SelfSubjectAccessReview>::all(client)
+        .create(&PostParams::default(), &review)
+        .await?
+        .status;
+
+    match status {
+        Some(status) if status.allowed => Ok(()),
+        status => Err(MacgyverCmdErrors::AccessDenied(format!(
+            "not allowed to list secrets in namespace {}{}",
+            namespace,
+            status
+                .and_then(|s| s.reason)
+                .map(|reason| format!(": {}", reason))
+                .unwrap_or_default()
+        ))),
+    }
+}
+
+/// For commands that also read other objects: a namespace where secrets can not be listed
+/// is skipped with a warning instead of failing the whole command
+pub async fn can_list_secrets(client: Client, namespace: &str) -> Result<bool> {
+    skip_denied(check_secret_access(client, namespace).await)
+}
+
+fn skip_denied(access: Result<()>) -> Result<bool> {
+    match access {
+        Ok(()) => Ok(true),
+        Err(MacgyverCmdErrors::AccessDenied(reason)) => {
+            eprintln!("{} {}, secrets skipped", "WARNING".yellow(), reason);
+            Ok(false)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+/// On a protected context, the user has to type the context name before secrets are revealed
+pub fn confirm_context() -> Result<()> {
+    confirm_named_context(&current_context()?)
+}
+
+/// `confirm_context` for a context other than the current one
+pub fn confirm_named_context(context: &str) -> Result<()> {
+    let pattern = env::var(PROTECTED_CONTEXTS_VAR).ok();
+    if !is_protected(pattern.as_deref(), context)? {
+        return Ok(());
+    }
+
+    eprint!(
+        "{} context {} is protected, type its name to reveal secrets: ",
+        "WARNING".yellow(),
+        context.bold()
+    );
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    if answer.trim().eq_ignore_ascii_case(context) {
+        Ok(())
+    } else {
+        Err(MacgyverCmdErrors::AccessDenied(format!(
+            "revealing secrets in context {} was not confirmed",
+            context
+        )))
+    }
+}
+
+fn is_protected(pattern: Option<&str>, context: &str) -> Result<bool> {
+    match pattern.filter(|pattern| !pattern.is_empty()) {
+        Some(pattern) => {
+            let regex = RegexBuilder::new(&format!("^(?:{})$", pattern))
+                .case_insensitive(true)
+                .build()
+                .map_err(|error| {
+                    MacgyverCmdErrors::CliParameterMissing(format!(
+                        "Invalid regex in {}: {}",
+                        PROTECTED_CONTEXTS_VAR, error
+                    ))
+                })?;
+            Ok(regex.is_match(context))
+        }
+        None => Ok(false),
+    }
+}
+
+fn current_context() -> Result<String> {
+    Ok(Kubeconfig::read()?.current_context.unwrap_or_default())
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct AuditEntry<'a> {
+    time: DateTime<Utc>,
+    /// `reveal`, or `search` when the value was matched against a pattern without being shown
+    action: &'a str,
+    os_user: &'a str,
+    kube_user: &'a str,
+    context: &'a str,
+    namespace: &'a str,
+    secret: &'a str,
+    key: &'a str,
+}
+
+/// JSON lines of who revealed or searched which secret key and when
+pub struct AuditLog {
+    path: PathBuf,
+    os_user: String,
+    kube_user: String,
+    context: String,
+}
+
+impl AuditLog {
+    pub fn open() -> Result<AuditLog> {
+        AuditLog::open_for(None)
+    }
+
+    /// Log entries of another context than the current one
+    pub fn open_for(context: Option<&str>) -> Result<AuditLog> {
+        let path = match env::var(AUDIT_LOG_VAR) {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => {
+                let home = env::var("HOME").map_err(|_| {
+                    MacgyverCmdErrors::CliParameterMissing(format!(
+                        "Missing env var HOME or {} for the audit log",
+                        AUDIT_LOG_VAR
+                    ))
+                })?;
+                PathBuf::from(home).join(".macgyver").join("audit.log")
+            }
+        };
+        let kubeconfig = Kubeconfig::read()?;
+        let context = match context {
+            Some(context) => context.to_string(),
+            None => kubeconfig.current_context.clone().unwrap_or_default(),
+        };
+        let kube_user = kubeconfig
+            .contexts
+            .iter()
+            .find(|named| named.name == context)
+            .map(|named| named.context.user.to_string())
+            .unwrap_or_default();
+        let os_user = env::var("USER")
+            .or_else(|_| env::var("USERNAME"))
+            .unwrap_or_default();
+
+        Ok(AuditLog {
+            path,
+            os_user,
+            kube_user,
+            context,
+        })
+    }
+
+    /// Written before the values are printed, a reveal is never missing from the log
+    pub fn record(&self, namespace: &str, secret: &str, keys: &[&str]) -> Result<()> {
+        self.write("reveal", namespace, secret, keys)
+    }
+
+    /// Searching values tells something about them, so it is logged like a reveal
+    pub fn record_search(&self, namespace: &str, secret: &str, keys: &[&str]) -> Result<()> {
+        self.write("search", namespace, secret, keys)
+    }
+
+    fn write(&self, action: &str, namespace: &str, secret: &str, keys: &[&str]) -> Result<()> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let time = Utc::now();
+        for key in keys {
+            let entry = AuditEntry {
+                time,
+                action,
+                os_user: &self.os_user,
+                kube_user: &self.kube_user,
+                context: &self.context,
+                namespace,
+                secret,
+                key,
+            };
+            let line = serde_json::to_string(&entry)
+                .map_err(|error| MacgyverCmdErrors::Processing(error.into()))?;
+            writeln!(file, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protected_contexts_match_the_whole_name() {
+        assert!(is_protected(Some("prod|live-.*"), "prod").unwrap());
+        assert!(is_protected(Some("prod|live-.*"), "PROD").unwrap());
+        assert!(is_protected(Some("prod|live-.*"), "live-eu").unwrap());
+        assert!(!is_protected(Some("prod|live-.*"), "preprod").unwrap());
+        assert!(!is_protected(Some(""), "prod").unwrap());
+        assert!(!is_protected(None, "prod").unwrap());
+        assert!(is_protected(Some("prod("), "prod").is_err());
+    }
+
+    #[test]
+    fn denied_namespaces_are_skipped_other_errors_are_not() {
+        assert!(skip_denied(Ok(())).unwrap());
+        let denied = MacgyverCmdErrors::AccessDenied("not allowed".to_string());
+        assert!(!skip_denied(Err(denied)).unwrap());
+        let failed = MacgyverCmdErrors::Processing("connection refused".into());
+        assert!(skip_denied(Err(failed)).is_err());
+    }
+
+    #[test]
+    fn audit_log_appends_one_line_per_key() {
+        let path = env::temp_dir()
+            .join(format!("macgyver-audit-{}", std::process::id()))
+            .join("audit.log");
+        let audit = AuditLog {
+            path: path.clone(),
+            os_user: "alice".to_string(),
+            kube_user: "alice@prod".to_string(),
+            context: "prod".to_string(),
+        };
+
+        audit
+            .record("billing", "db", &["username", "password"])
+            .unwrap();
+        audit.record("billing", "db", &[]).unwrap();
+        audit.record_search("billing", "api", &["token"]).unwrap();
+
+        let log = fs::read_to_string(&path).unwrap();
+        let keys: Vec<String> = log
+            .lines()
+            .map(|line| {
+                let entry: serde_json::Value = serde_json::from_str(line).unwrap();
+                assert_eq!(entry["os_user"], "alice");
+                assert_eq!(entry["kube_user"], "alice@prod");
+                assert_eq!(entry["context"], "prod");
+                format!(
+                    "{} {}/{}",
+                    entry["action"].as_str().unwrap(),
+                    entry["secret"].as_str().unwrap(),
+                    entry["key"].as_str().unwrap()
+                )
+            })
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                "reveal db/username",
+                "reveal db/password",
+                "search api/token"
+            ]
+        );
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -5,6 +5,8 @@ use macgyver_rs::Result;
 use macgyver_rs::cli_args::Entities::Pods;
 use macgyver_rs::pods;
 
+#[cfg(feature = "cpumem")]
+use macgyver_rs::cli_args::Entities::Cost;
 #[cfg(feature = "cpumem")]
 use macgyver_rs::cli_args::Entities::CpuMem;
 #[cfg(feature = "cpumem")]
@@ -25,6 +27,31 @@ use macgyver_rs::cli_args::Entities::Logs;
 #[cfg(feature = "logs")]
 use macgyver_rs::logs;
 
+#[cfg(feature = "nodes")]
+use macgyver_rs::cli_args::Entities::Nodes;
+#[cfg(feature = "nodes")]
+use macgyver_rs::nodes;
+
+#[cfg(feature = "certs")]
+use macgyver_rs::certs;
+#[cfg(feature = "certs")]
+use macgyver_rs::cli_args::Entities::Certs;
+
+#[cfg(feature = "env")]
+use macgyver_rs::cli_args::Entities::Env;
+#[cfg(feature = "env")]
+use macgyver_rs::env;
+
+#[cfg(feature = "orphans")]
+use macgyver_rs::cli_args::Entities::Orphans;
+#[cfg(feature = "orphans")]
+use macgyver_rs::orphans;
+
+#[cfg(feature = "grep")]
+use macgyver_rs::cli_args::Entities::Grep;
+#[cfg(feature = "grep")]
+use macgyver_rs::grep;
+
 fn main() -> Result<()> {
     let args = CliArgs::parse();
 
@@ -37,11 +64,29 @@ fn main() -> Result<()> {
         #[cfg(feature = "cpumem")]
         CpuMem(args) => cpumem::process(args)?,
 
+        #[cfg(feature = "cpumem")]
+        Cost(args) => cpumem::cost::process(args)?,
+
         #[cfg(feature = "secret")]
         Secret(args) => secret::process(args)?,
 
         #[cfg(feature = "logs")]
-        Logs(args) => logs::process(args)?,
+        Logs(args) => logs::process(*args)?,
+
+        #[cfg(feature = "nodes")]
+        Nodes(args) => nodes::process(args)?,
+
+        #[cfg(feature = "certs")]
+        Certs(args) => certs::process(args)?,
+
+        #[cfg(feature = "env")]
+        Env(args) => env::process(args)?,
+
+        #[cfg(feature = "orphans")]
+        Orphans(args) => orphans::process(args)?,
+
+        #[cfg(feature = "grep")]
+        Grep(args) => grep::process(args)?,
     };
     Ok(())
 }
@@ -0,0 +1,263 @@
+use crate::access::can_list_secrets;
+use crate::certificate::{looks_like_pem, parse_pem, Certificate};
+use crate::cli_args::CertsArgs;
+use crate::errors::MacgyverCmdErrors;
+use crate::{get_k8s_env, Result};
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use k8s_openapi::api::core::v1::{ConfigMap, Namespace, Secret};
+use kube::api::ListParams;
+use kube::{api::Api, Client};
+
+/// Days left under which expiry is shown in yellow when `--warn-days` is not set
+const DEFAULT_WARN_DAYS: i64 = 30;
+
+/// A PEM bundle: the leaf is the first certificate, the expiry the soonest of the chain
+#[derive(Debug)]
+struct CertRow {
+    namespace: String,
+    object: String,
+    key: String,
+    chain: usize,
+    leaf: Option<Certificate>,
+    not_after: Option<DateTime<Utc>>,
+    error: Option<String>,
+}
+
+#[tokio::main]
+pub async fn process(
+    CertsArgs {
+        namespaces,
+        all_namespaces,
+        warn_days,
+    }: CertsArgs,
+) -> Result<()> {
+    let context = get_k8s_env()?;
+    let client = Client::try_default().await?;
+
+    let namespaces = match all_namespaces {
+        true => Api::<Namespace>::all(client.clone())
+            .list(&ListParams::default())
+            .await?
+            .into_iter()
+            .flat_map(|namespace| namespace.metadata.name)
+            .collect(),
+        false => namespaces,
+    };
+
+    let mut rows = vec![];
+    for namespace in &namespaces {
+        // Same access check as `secret`, the configmaps are still reported when it fails
+        let secrets = match can_list_secrets(client.clone(), namespace).await? {
+            true => Some(Api::namespaced(client.clone(), namespace)),
+            false => None,
+        };
+        rows.extend(collect(secrets, Api::namespaced(client.clone(), namespace)).await?);
+    }
+    sort(&mut rows);
+
+    let now = Utc::now();
+    let threshold = warn_days.unwrap_or(DEFAULT_WARN_DAYS);
+    println!("ENVIRONMENT: {context}");
+    println!(
+        "{0: <8} {1: <22} {2: <30} {3: <60} {4: <30} {5: <6} {6: <60} sans",
+        "days", "expires", "namespace", "object", "key", "chain", "subject"
+    );
+    rows.iter().for_each(|row| print_row(row, now, threshold));
+
+    match warn_days {
+        Some(warn_days) => match expiring(&rows, warn_days, now) {
+            0 => Ok(()),
+            count => Err(MacgyverCmdErrors::ExpiringCertificates(count, warn_days)),
+        },
+        None => Ok(()),
+    }
+}
+
+async fn collect(secrets: Option<Api<Secret>>, configmaps: Api<ConfigMap>) -> Result<Vec<CertRow>> {
+    let mut rows = vec![];
+    let secrets = match secrets {
+        Some(secrets) => secrets.list(&ListParams::default()).await?.items,
+        None => vec![],
+    };
+    for secret in secrets {
+        let namespace = secret.metadata.namespace.unwrap_or_default();
+        let object = format!("secret/{}", secret.metadata.name.unwrap_or_default());
+        let tls = secret.type_.as_deref() == Some("kubernetes.io/tls");
+        for (key, value) in secret.data.unwrap_or_default() {
+            // tls.crt of a tls secret is reported even when it is not valid PEM
+            let force = tls && key == "tls.crt";
+            rows.extend(cert_row(&namespace, &object, &key, &value.0, force));
+        }
+    }
+    for configmap in configmaps.list(&ListParams::default()).await? {
+        let namespace = configmap.metadata.namespace.unwrap_or_default();
+        let object = format!("configmap/{}", configmap.metadata.name.unwrap_or_default());
+        let data = configmap
+            .data
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(key, value)| (key, value.into_bytes()));
+        let binary_data = configmap
+            .binary_data
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(key, value)| (key, value.0));
+        for (key, value) in data.chain(binary_data) {
+            rows.extend(cert_row(&namespace, &object, &key, &value, false));
+        }
+    }
+    Ok(rows)
+}
+
+fn cert_row(
+    namespace: &str,
+    object: &str,
+    key: &str,
+    value: &[u8],
+    force: bool,
+) -> Option<CertRow> {
+    if !force && !looks_like_pem(value) {
+        return None;
+    }
+    let parsed = parse_pem(value);
+    let error = match parsed.iter().find_map(|c| c.as_ref().err()) {
+        Some(error) => Some(error.to_string()),
+        None if parsed.is_empty() => Some("no PEM certificate".to_string()),
+        None => None,
+    };
+    let certificates: Vec<Certificate> = parsed.into_iter().flatten().collect();
+    Some(CertRow {
+        namespace: namespace.to_string(),
+        object: object.to_string(),
+        key: key.to_string(),
+        chain: certificates.len(),
+        not_after: certificates.iter().map(|c| c.not_after).min(),
+        leaf: certificates.into_iter().next(),
+        error,
+    })
+}
+
+/// Soonest expiry first, unreadable bundles last
+fn sort(rows: &mut [CertRow]) {
+    rows.sort_by(|a, b| match (a.not_after, b.not_after) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (a, b) => a.is_none().cmp(&b.is_none()),
+    });
+}
+
+/// Certificates expiring within `warn_days`, and bundles without a readable certificate
+/// (like a broken tls.crt) as they can not be checked
+fn expiring(rows: &[CertRow], warn_days: i64, now: DateTime<Utc>) -> usize {
+    rows.iter()
+        .filter(|row| match row.not_after {
+            Some(not_after) => (not_after - now).num_days() < warn_days,
+            None => true,
+        })
+        .count()
+}
+
+fn print_row(row: &CertRow, now: DateTime<Utc>, warn_days: i64) {
+    let days = row.not_after.map(|not_after| (not_after - now).num_days());
+    let days_text = format!(
+        "{: <8}",
+        days.map(|days| days.to_string())
+            .unwrap_or("N/A".to_string())
+    );
+    let days_text = match days {
+        Some(days) if days < 0 => days_text.red(),
+        Some(days) if days < warn_days => days_text.yellow(),
+        Some(_) => days_text.green(),
+        None => days_text.red(),
+    };
+    let (subject, sans) = match &row.leaf {
+        Some(leaf) => (leaf.subject.to_string(), leaf.sans.join(", ")),
+        None => (String::new(), String::new()),
+    };
+    println!(
+        "{0} {1: <22} {2: <30} {3: <60} {4: <30} {5: <6} {6: <60} {7}",
+        days_text,
+        row.not_after
+            .map(|not_after| not_after.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or("N/A".to_string()),
+        row.namespace,
+        row.object,
+        row.key,
+        row.chain,
+        subject,
+        sans
+    );
+    if let Some(error) = &row.error {
+        println!("{0: <8} {1}", "", error.red());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::certificate::tests::CERTIFICATE;
+    use chrono::{Duration, TimeZone};
+
+    fn row(object: &str, not_after: Option<DateTime<Utc>>) -> CertRow {
+        CertRow {
+            namespace: "billing".to_string(),
+            object: object.to_string(),
+            key: "tls.crt".to_string(),
+            chain: 1,
+            leaf: None,
+            not_after,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn chains_and_values_that_are_not_pem() {
+        let chain = format!("{}{}", CERTIFICATE, CERTIFICATE);
+        let row = cert_row("billing", "secret/tls", "tls.crt", chain.as_bytes(), false).unwrap();
+        assert_eq!(row.chain, 2);
+        assert_eq!(
+            row.leaf.unwrap().subject,
+            "CN=billing.example.com, O=Example"
+        );
+        assert!(row.error.is_none());
+
+        assert!(cert_row(
+            "billing",
+            "configmap/app",
+            "url",
+            b"https://example.com",
+            false
+        )
+        .is_none());
+        let broken = cert_row("billing", "secret/tls", "tls.crt", b"garbage", true).unwrap();
+        assert_eq!(broken.chain, 0);
+        assert_eq!(broken.error.as_deref(), Some("no PEM certificate"));
+    }
+
+    #[test]
+    fn soonest_expiry_first_and_warn_threshold() {
+        let now = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+        let mut rows = vec![
+            row("secret/later", Some(now + Duration::days(90))),
+            row("secret/broken", None),
+            row("secret/expired", Some(now - Duration::days(1))),
+            row("secret/soon", Some(now + Duration::days(10))),
+        ];
+
+        sort(&mut rows);
+
+        let objects: Vec<&str> = rows.iter().map(|row| row.object.as_str()).collect();
+        assert_eq!(
+            objects,
+            vec![
+                "secret/expired",
+                "secret/soon",
+                "secret/later",
+                "secret/broken"
+            ]
+        );
+        // The broken bundle counts whatever the threshold
+        assert_eq!(expiring(&rows, 30, now), 3);
+        assert_eq!(expiring(&rows, 0, now), 2);
+    }
+}
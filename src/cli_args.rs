@@ -1,5 +1,10 @@
-use clap::{Parser, Subcommand};
+use crate::quantity::Units;
+use clap::{Parser, Subcommand, ValueEnum};
+use regex::Regex;
 use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 
 /// A CLI tool
 #[derive(Parser, Debug)]
@@ -16,11 +21,15 @@ pub enum Entities {
 
     /// Collect configmaps
     #[cfg(feature = "configmap")]
-    Configmap(NamespaceArgs),
+    Configmap(ConfigmapArgs),
 
     /// Collect resources(cpu, requests) info
     #[cfg(feature = "cpumem")]
-    CpuMem(NamespaceArgs),
+    CpuMem(CpuMemArgs),
+
+    /// Estimate the monthly cost of the requests (or usage) per service, namespace and context
+    #[cfg(feature = "cpumem")]
+    Cost(CostArgs),
 
     /// Collect secret info
     #[cfg(feature = "secret")]
@@ -28,7 +37,27 @@ pub enum Entities {
 
     /// Collect logs through different services
     #[cfg(feature = "logs")]
-    Logs(Services),
+    Logs(Box<Services>),
+
+    /// Collect node capacity, allocated requests/limits, taints and conditions
+    #[cfg(feature = "nodes")]
+    Nodes(NodesArgs),
+
+    /// Report TLS certificates of secrets and configmaps, soonest expiry first
+    #[cfg(feature = "certs")]
+    Certs(CertsArgs),
+
+    /// Show the environment a container receives and where each variable comes from
+    #[cfg(feature = "env")]
+    Env(EnvArgs),
+
+    /// Report configmaps and secrets nobody references, and references to missing ones
+    #[cfg(feature = "orphans")]
+    Orphans(OrphansArgs),
+
+    /// Search configmap (and secret) keys and values with a regex across namespaces and contexts
+    #[cfg(feature = "grep")]
+    Grep(GrepArgs),
 }
 
 #[derive(Parser, Debug)]
@@ -39,6 +68,62 @@ pub struct Services {
 
     #[arg(short, long)]
     pub pattern: Option<String>,
+
+    /// Print NUM lines of trailing context after each pattern match
+    #[arg(short = 'A', long, value_name = "NUM", requires = "pattern")]
+    pub after_context: Option<usize>,
+
+    /// Print NUM lines of leading context before each pattern match
+    #[arg(short = 'B', long, value_name = "NUM", requires = "pattern")]
+    pub before_context: Option<usize>,
+
+    /// Print NUM lines of context around each pattern match
+    #[arg(short = 'C', long, value_name = "NUM", requires = "pattern")]
+    pub context: Option<usize>,
+
+    /// Label selector template used to find the pods of a service.
+    /// `{namespace}` and `{service}` are substituted, `@service` uses the selector of the k8s Service
+    #[arg(
+        long,
+        default_value = "app.kubernetes.io/instance={namespace}-{service}"
+    )]
+    pub selector: String,
+
+    /// Selector template for a namespace or a namespace:service, as SCOPE=TEMPLATE
+    #[arg(long, value_parser = validate_selector_override)]
+    pub selector_override: Vec<SelectorOverride>,
+
+    /// Write the followed logs to one file per pod/container in this directory
+    #[arg(long, conflicts_with = "bundle")]
+    pub out_dir: Option<PathBuf>,
+
+    /// Write a snapshot (no follow) of the logs of every container and a manifest to this .tar.gz archive
+    #[arg(long, conflicts_with_all = ["pattern", "after_context", "before_context", "context"])]
+    pub bundle: Option<PathBuf>,
+
+    /// Show a live per-service table of log volume, error/warn counts and top messages
+    #[arg(long, conflicts_with_all = ["out_dir", "bundle"])]
+    pub stats: bool,
+
+    /// Regex matching error lines (or level field values) for --stats
+    #[arg(long, value_parser = Regex::new, default_value = r"(?i)\b(error|fatal|panic|critical)\b")]
+    pub error_regex: Regex,
+
+    /// Regex matching warning lines (or level field values) for --stats
+    #[arg(long, value_parser = Regex::new, default_value = r"(?i)\bwarn(ing)?\b")]
+    pub warn_regex: Regex,
+
+    /// JSON field holding the log level for --stats, e.g. `level`
+    #[arg(long)]
+    pub level_field: Option<String>,
+
+    /// Number of top repeated messages shown per service for --stats
+    #[arg(long, default_value_t = 5)]
+    pub top: usize,
+
+    /// How many times a broken log stream is retried (with backoff) before giving up
+    #[arg(long, default_value_t = 5)]
+    pub retries: u32,
 }
 
 #[derive(Parser, Debug, Clone)]
@@ -48,8 +133,8 @@ pub struct NamespaceAndService {
 }
 
 fn validate_services(args: &str) -> io::Result<NamespaceAndService> {
-    let args_token: Vec<&str> = args.split(":").into_iter().collect();
-    match (args_token.get(0), args_token.get(1)) {
+    let args_token: Vec<&str> = args.split(':').collect();
+    match (args_token.first(), args_token.get(1)) {
         (Some(namespace), Some(service)) if args_token.len() == 2 => Ok(NamespaceAndService {
             namespace: namespace.to_string(),
             service: service.to_string(),
@@ -61,6 +146,27 @@ fn validate_services(args: &str) -> io::Result<NamespaceAndService> {
     }
 }
 
+#[derive(Parser, Debug, Clone)]
+pub struct SelectorOverride {
+    pub scope: String,
+    pub template: String,
+}
+
+fn validate_selector_override(args: &str) -> io::Result<SelectorOverride> {
+    match args.split_once('=') {
+        Some((scope, template)) if !scope.is_empty() && !template.is_empty() => {
+            Ok(SelectorOverride {
+                scope: scope.to_string(),
+                template: template.to_string(),
+            })
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Invalid argument . Selector override has syntax namespace[:service]=template",
+        )),
+    }
+}
+
 #[derive(Parser, Debug)]
 pub struct NamespaceWithPods {
     #[arg(long)]
@@ -71,16 +177,347 @@ pub struct NamespaceWithPods {
 }
 
 #[derive(Parser, Debug)]
-pub struct NamespaceArgs {
+pub struct ConfigmapArgs {
     #[arg(short, long)]
     pub namespace: String,
+
+    /// Only the configmaps with this name, can be repeated
+    #[arg(long = "name")]
+    pub names: Vec<String>,
+
+    /// Check that yaml, json, properties and toml values parse, exits with an error otherwise
+    #[arg(long, conflicts_with_all = ["diff_context", "diff_namespace", "export"])]
+    pub validate: bool,
+
+    #[command(flatten)]
+    pub diff: DiffArgs,
+
+    #[command(flatten)]
+    pub export: ExportArgs,
+}
+
+/// Compares the keys of the namespace with another context and/or namespace
+#[derive(Parser, Debug)]
+pub struct DiffArgs {
+    /// Diff the keys against the same namespace (or --diff-namespace) in this context
+    #[arg(long)]
+    pub diff_context: Option<String>,
+
+    /// Diff the keys against this namespace
+    #[arg(long)]
+    pub diff_namespace: Option<String>,
+}
+
+/// Writes the objects instead of printing them
+#[derive(Parser, Debug)]
+pub struct ExportArgs {
+    /// Export as a .env file or as manifests that can be applied again (managedFields, resourceVersion, uid and annotations stripped)
+    #[arg(long, value_enum, conflicts_with_all = ["diff_context", "diff_namespace"])]
+    pub export: Option<ExportFormat>,
+
+    /// Write one file per object in this directory instead of stdout
+    #[arg(long, requires = "export")]
+    pub out_dir: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Dotenv,
+    Json,
+    Yaml,
+}
+
+#[derive(Parser, Debug)]
+pub struct CpuMemArgs {
+    #[arg(short, long)]
+    pub namespace: String,
+
+    /// Usage percentage of the request/limit shown in yellow
+    #[arg(long, default_value_t = 70.0)]
+    pub warn_percent: f64,
+
+    /// Usage percentage of the request/limit shown in red
+    #[arg(long, default_value_t = 90.0)]
+    pub critical_percent: f64,
+
+    /// Units used to print cpu and memory, as cpu:memory (cpu: m, cores; memory: bytes, k, M, G, T, Ki, Mi, Gi, Ti)
+    #[arg(long, value_parser = Units::from_str, default_value = "m:Mi")]
+    pub units: Units,
+
+    /// Sort pods by name or by a column, highest first
+    #[arg(long, value_enum)]
+    pub sort_by: Option<SortBy>,
+
+    /// Show requests/limits totals per workload and namespace against the ResourceQuota and LimitRange
+    #[arg(long)]
+    pub totals: bool,
+
+    /// Also list init containers (not counted in the pod total)
+    #[arg(long)]
+    pub init_containers: bool,
+
+    /// Check requests/limits against the resource policy, exits with an error on violations
+    #[arg(long, conflicts_with_all = ["totals", "hpa", "sort_by", "init_containers", "warn_percent", "critical_percent"])]
+    pub lint: bool,
+
+    /// YAML file with the --lint rules (require_memory_limit, forbid_cpu_limit, max_limit_request_ratio, max_request)
+    #[arg(long, requires = "lint")]
+    pub lint_config: Option<PathBuf>,
+
+    /// Sample usage over --window and print requests/limits recommendations as Helm values patches
+    #[arg(long, conflicts_with_all = ["lint", "totals", "hpa"])]
+    pub recommend: bool,
+
+    /// How long --recommend samples usage, e.g. 30s, 10m, 1h
+    #[arg(long, value_parser = validate_duration, default_value = "10m")]
+    pub window: Duration,
+
+    /// Time between two --recommend samples, e.g. 15s, 1m
+    #[arg(long, value_parser = validate_duration, default_value = "30s")]
+    pub interval: Duration,
+
+    /// Headroom in percent added to the p95 usage for the recommended requests
+    #[arg(long, default_value_t = 20.0)]
+    pub request_headroom: f64,
+
+    /// Headroom in percent added to the max usage for the recommended limits
+    #[arg(long, default_value_t = 30.0)]
+    pub limit_headroom: f64,
+
+    /// Show the HorizontalPodAutoscalers, flag the ones pinned at max or without cpu requests to scale on
+    #[arg(long)]
+    pub hpa: bool,
+}
+
+fn validate_duration(args: &str) -> io::Result<Duration> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Invalid argument . Duration has syntax NUMBER followed by s, m or h",
+        )
+    };
+    let split = args.len().saturating_sub(1);
+    let (number, unit) = (args.get(..split).ok_or_else(invalid)?, &args[split..]);
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "s" => Some(number),
+        "m" => number.checked_mul(60),
+        "h" => number.checked_mul(3600),
+        _ => return Err(invalid()),
+    }
+    .ok_or_else(invalid)?;
+    if seconds == 0 {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+#[derive(Parser, Debug)]
+pub struct CostArgs {
+    #[arg(short, long = "namespace", required = true)]
+    pub namespaces: Vec<String>,
+
+    /// Kubeconfig context to report on, can be repeated. Defaults to the current context
+    #[arg(long = "context")]
+    pub contexts: Vec<String>,
+
+    /// YAML file with the prices (cpu_core_hour, memory_gib_hour, currency)
+    #[arg(long)]
+    pub prices: PathBuf,
+
+    /// Use the current usage of the pods instead of the requests when metrics.k8s.io is available
+    #[arg(long)]
+    pub usage: bool,
+
+    /// Print CSV instead of a table
+    #[arg(long)]
+    pub csv: bool,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum SortBy {
+    Pod,
+    LimitCpu,
+    LimitMemory,
+    RequestCpu,
+    RequestMemory,
+    UsageCpu,
+    UsageMemory,
+}
+
+#[derive(Parser, Debug)]
+pub struct NodesArgs {
+    /// Namespace whose pods are listed under each node
+    #[arg(short, long)]
+    pub namespace: String,
+
+    /// Units used to print cpu and memory, as cpu:memory (cpu: m, cores; memory: bytes, k, M, G, T, Ki, Mi, Gi, Ti)
+    #[arg(long, value_parser = Units::from_str, default_value = "m:Gi")]
+    pub units: Units,
+}
+
+#[derive(Parser, Debug)]
+pub struct CertsArgs {
+    #[arg(short, long = "namespace", required_unless_present = "all_namespaces")]
+    pub namespaces: Vec<String>,
+
+    /// Look for certificates in every namespace
+    #[arg(short = 'A', long, conflicts_with = "namespaces")]
+    pub all_namespaces: bool,
+
+    /// Exit with an error when a certificate expires within this many days or can not be read
+    #[arg(long)]
+    pub warn_days: Option<i64>,
+}
+
+#[derive(Parser, Debug)]
+pub struct GrepArgs {
+    /// Regex matched against the keys and each line of the values
+    pub pattern: String,
+
+    #[arg(short, long = "namespace", required_unless_present = "all_namespaces")]
+    pub namespaces: Vec<String>,
+
+    /// Search every namespace
+    #[arg(short = 'A', long, conflicts_with = "namespaces")]
+    pub all_namespaces: bool,
+
+    /// Kubeconfig context to search, can be repeated. Defaults to the current context
+    #[arg(long = "context")]
+    pub contexts: Vec<String>,
+
+    #[arg(short, long)]
+    pub ignore_case: bool,
+
+    /// Also search the keys of the secrets, needs the permission to list them
+    #[cfg(feature = "secret")]
+    #[arg(long)]
+    pub secrets: bool,
+
+    /// Also search the values of the secrets, matches are listed without the value. Confirmed on protected contexts and audited
+    #[cfg(feature = "secret")]
+    #[arg(long)]
+    pub secret_values: bool,
+}
+
+/// References are read from the pod specs of pods, deployments, statefulsets, daemonsets,
+/// jobs and cronjobs, from the serviceaccounts and from the ingress tls
+#[derive(Parser, Debug)]
+pub struct OrphansArgs {
+    #[arg(short, long = "namespace", required_unless_present = "all_namespaces")]
+    pub namespaces: Vec<String>,
+
+    /// Report on every namespace
+    #[arg(short = 'A', long, conflicts_with = "namespaces")]
+    pub all_namespaces: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct EnvArgs {
+    #[arg(short, long)]
+    pub namespace: String,
+
+    /// pod/NAME, deployment/NAME, statefulset/NAME or daemonset/NAME, a bare NAME is a pod
+    #[arg(value_parser = validate_workload)]
+    pub workload: Workload,
+
+    /// Only this container, every container (init containers included) by default
+    #[arg(short, long)]
+    pub container: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WorkloadKind {
+    Pod,
+    Deployment,
+    StatefulSet,
+    DaemonSet,
+}
+
+impl WorkloadKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            WorkloadKind::Pod => "pod",
+            WorkloadKind::Deployment => "deployment",
+            WorkloadKind::StatefulSet => "statefulset",
+            WorkloadKind::DaemonSet => "daemonset",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Workload {
+    pub kind: WorkloadKind,
+    pub name: String,
+}
+
+fn validate_workload(args: &str) -> io::Result<Workload> {
+    let (kind, name) = args.split_once('/').unwrap_or(("pod", args));
+    let kind = match kind {
+        "pod" | "pods" | "po" => Some(WorkloadKind::Pod),
+        "deployment" | "deployments" | "deploy" => Some(WorkloadKind::Deployment),
+        "statefulset" | "statefulsets" | "sts" => Some(WorkloadKind::StatefulSet),
+        "daemonset" | "daemonsets" | "ds" => Some(WorkloadKind::DaemonSet),
+        _ => None,
+    };
+    match kind {
+        Some(kind) if !name.is_empty() => Ok(Workload {
+            kind,
+            name: name.to_string(),
+        }),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Invalid argument . Workload has syntax [pod|deployment|statefulset|daemonset/]name",
+        )),
+    }
 }
 
 #[derive(Parser, Debug)]
 pub struct NamespaceWithEncodedSecretArgs {
+    /// Also print the base64 of the revealed values
     #[arg(long)]
     pub with_encoded: bool,
 
+    /// Print the value of this key in cleartext, can be repeated. Other values are masked.
+    /// Reveals are written to the audit log and need confirmation on protected contexts
+    #[arg(long, value_name = "KEY", conflicts_with_all = ["diff_context", "diff_namespace", "export"])]
+    pub reveal: Vec<String>,
+
+    /// Print every value in cleartext
+    #[arg(long, conflicts_with_all = ["reveal", "diff_context", "diff_namespace", "export"])]
+    pub reveal_all: bool,
+
+    // Secret values are compared by salted hash, never printed
+    #[command(flatten)]
+    pub diff: DiffArgs,
+
+    // Exports reveal every value: they are confirmed and audited like --reveal-all
+    #[command(flatten)]
+    pub export: ExportArgs,
+
+    /// Only the secrets with this name, can be repeated
+    #[arg(long = "name")]
+    pub names: Vec<String>,
+
+    /// Secret type to show (Opaque, kubernetes.io/tls, kubernetes.io/dockerconfigjson, kubernetes.io/basic-auth, ...), can be repeated
+    #[arg(long = "type", default_value = "Opaque")]
+    pub types: Vec<String>,
+
     #[arg(short, long)]
     pub namespace: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_in_seconds_minutes_and_hours() {
+        assert_eq!(validate_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(validate_duration("10m").unwrap(), Duration::from_secs(600));
+        assert_eq!(validate_duration("2h").unwrap(), Duration::from_secs(7200));
+        for invalid in ["0s", "10", "m", "10d", "-1m", "18446744073709551615h"] {
+            assert!(validate_duration(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -1,33 +1,155 @@
-use crate::cli_args::NamespaceArgs;
+mod pretty;
+
+use crate::cli_args::ConfigmapArgs;
+use crate::diff::{self, Side};
+use crate::errors::MacgyverCmdErrors;
+use crate::export::{self, Exported};
 use crate::{get_k8s_env, Result};
+use colored::Colorize;
 use k8s_openapi::api::core::v1::ConfigMap;
 use kube::api::ListParams;
 use kube::{api::Api, Client};
+use std::collections::BTreeMap;
 
 #[tokio::main]
-pub async fn process(NamespaceArgs { namespace }: NamespaceArgs) -> Result<()> {
+pub async fn process(
+    ConfigmapArgs {
+        namespace,
+        names,
+        validate,
+        diff,
+        export,
+    }: ConfigmapArgs,
+) -> Result<()> {
     let context = get_k8s_env()?;
     let client = Client::try_default().await?;
+    let selected = |c: &ConfigMap| {
+        names.is_empty() || c.metadata.name.as_ref().is_some_and(|n| names.contains(n))
+    };
+
+    if let Some((target, label, target_namespace)) = diff::target(&diff, &namespace).await? {
+        let left = Side {
+            label: format!("{}/{}", context, namespace),
+            objects: values(client, &namespace, &selected).await?,
+        };
+        let right = Side {
+            label,
+            objects: values(target, &target_namespace, &selected).await?,
+        };
+        diff::print(&left, &right);
+        return Ok(());
+    }
+
     let api: Api<ConfigMap> = Api::namespaced(client, namespace.as_str());
 
+    if let Some(format) = export.export {
+        let exported = api
+            .list(&ListParams::default())
+            .await?
+            .into_iter()
+            .filter(selected)
+            .map(|mut object| {
+                export::clean(&mut object);
+                Exported {
+                    values: data(&object),
+                    object,
+                }
+            })
+            .collect();
+        return export::write(format, export.out_dir.as_deref(), exported);
+    }
+
     println!("ENVIRONMENT: {context}");
 
-    api.list(&ListParams::default())
+    let mut invalid = 0;
+    let configmaps = api.list(&ListParams::default()).await?;
+    for p in configmaps.into_iter().filter(selected) {
+        let Some(name) = p.metadata.name else {
+            continue;
+        };
+        println!("{name}: "); // print project
+        if p.data.is_none() && p.binary_data.is_none() {
+            println!("      None");
+        }
+        for (key, value) in p.data.unwrap_or_default() {
+            if !print_value(&key, &value, validate) {
+                invalid += 1;
+            }
+        }
+        for (key, value) in p.binary_data.unwrap_or_default() {
+            let binary = format!(
+                "<binary, {} bytes, {}>",
+                value.0.len(),
+                pretty::binary_type(&value.0)
+            );
+            println!("      {0:_<50} {1}", key, binary.dimmed());
+        }
+        println!()
+    }
+
+    match invalid {
+        0 => Ok(()),
+        count => Err(MacgyverCmdErrors::InvalidConfigValues(count)),
+    }
+}
+
+/// Structured and multi-line values are printed under their key. False when `validate`
+/// finds a value that does not parse
+fn print_value(key: &str, value: &str, validate: bool) -> bool {
+    let format = pretty::detect(key, value);
+    let error = match (validate, format) {
+        (true, Some(format)) => pretty::validate(format, value).err(),
+        _ => None,
+    };
+    match format {
+        Some(format) => {
+            let label = match &error {
+                Some(error) => format!("{}, invalid: {}", format.label(), error).red(),
+                None if validate => format!("{}, valid", format.label()).green(),
+                None => format.label().dimmed(),
+            };
+            println!("      {0:_<50} {1}", key, label);
+            pretty::highlight(format, value)
+                .iter()
+                .for_each(|line| println!("          {}", line));
+        }
+        None if value.trim_end().contains('\n') => {
+            println!("      {0:_<50}", key);
+            value
+                .lines()
+                .for_each(|line| println!("          {}", line));
+        }
+        None => println!("      {0:_<50} {1: <10}", key, value),
+    }
+    error.is_none()
+}
+
+/// data and binaryData of every configmap, as bytes
+async fn values(
+    client: Client,
+    namespace: &str,
+    selected: impl Fn(&ConfigMap) -> bool,
+) -> Result<BTreeMap<String, BTreeMap<String, Vec<u8>>>> {
+    let api: Api<ConfigMap> = Api::namespaced(client, namespace);
+    Ok(api
+        .list(&ListParams::default())
         .await?
         .into_iter()
-        .for_each(|p| {
-            if let Some(name) = p.metadata.name {
-                println!("{name}: "); // print project
-                if let Some(config) = p.data {
-                    config.into_iter().for_each(|(key, value)| {
-                        println!("      {0:_<50} {1: <10}", key, value);
-                    })
-                } else {
-                    println!("      None");
-                }
-                println!()
-            }
-        });
+        .filter(|c| selected(c))
+        .flat_map(|c| Some((c.metadata.name.clone()?, data(&c))))
+        .collect())
+}
 
-    Ok(())
+fn data(configmap: &ConfigMap) -> BTreeMap<String, Vec<u8>> {
+    let data = configmap
+        .data
+        .iter()
+        .flatten()
+        .map(|(key, value)| (key.clone(), value.clone().into_bytes()));
+    let binary_data = configmap
+        .binary_data
+        .iter()
+        .flatten()
+        .map(|(key, value)| (key.clone(), value.0.clone()));
+    data.chain(binary_data).collect()
 }
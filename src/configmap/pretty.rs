@@ -0,0 +1,270 @@
+use colored::Colorize;
+use regex::Regex;
+use serde::Deserialize;
+use std::sync::OnceLock;
+
+static PROPERTIES_ASSIGNMENT: OnceLock<Regex> = OnceLock::new();
+static PROPERTIES_ESCAPE: OnceLock<Regex> = OnceLock::new();
+static YAML_KEY: OnceLock<Regex> = OnceLock::new();
+static JSON_KEY: OnceLock<Regex> = OnceLock::new();
+static PROPERTIES_KEY: OnceLock<Regex> = OnceLock::new();
+static TOML_KEY: OnceLock<Regex> = OnceLock::new();
+
+/// Compiled once, every value of every configmap goes through the same patterns
+fn regex(cell: &'static OnceLock<Regex>, pattern: &str) -> &'static Regex {
+    cell.get_or_init(|| Regex::new(pattern).expect("Valid configmap format regex"))
+}
+
+/// Structured formats of configmap values, usually whole files mounted as a volume
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Yaml,
+    Json,
+    Properties,
+    Toml,
+}
+
+impl Format {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Format::Yaml => "yaml",
+            Format::Json => "json",
+            Format::Properties => "properties",
+            Format::Toml => "toml",
+        }
+    }
+}
+
+/// By the extension of the key, else by the content of multi-line (or JSON) values.
+/// Sniffed formats always parse, except properties which parse anyway
+pub fn detect(key: &str, value: &str) -> Option<Format> {
+    let extension = key
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_lowercase());
+    match extension.as_deref() {
+        Some("yaml" | "yml") => return Some(Format::Yaml),
+        Some("json") => return Some(Format::Json),
+        Some("properties") => return Some(Format::Properties),
+        Some("toml") => return Some(Format::Toml),
+        _ => {}
+    }
+
+    let trimmed = value.trim();
+    if (trimmed.starts_with('{') || trimmed.starts_with('['))
+        && validate(Format::Json, trimmed).is_ok()
+    {
+        return Some(Format::Json);
+    }
+    if !trimmed.contains('\n') {
+        return None;
+    }
+    if validate(Format::Toml, value).is_ok() {
+        return Some(Format::Toml);
+    }
+    let assignment = regex(&PROPERTIES_ASSIGNMENT, r"^\s*[\w.-]+\s*[=:]");
+    let lines = || {
+        value
+            .lines()
+            .filter(|line| !line.trim().is_empty() && !is_comment(Format::Properties, line))
+    };
+    if lines().all(|line| assignment.is_match(line)) && lines().any(|line| line.contains('=')) {
+        return Some(Format::Properties);
+    }
+    match serde_yaml::from_str::<serde_yaml::Value>(value) {
+        Ok(serde_yaml::Value::Mapping(_) | serde_yaml::Value::Sequence(_)) => Some(Format::Yaml),
+        _ => None,
+    }
+}
+
+/// The parse error of the value, with its line when the parser tells it
+pub fn validate(format: Format, value: &str) -> Result<(), String> {
+    match format {
+        Format::Yaml => serde_yaml::Deserializer::from_str(value)
+            .try_for_each(|document| serde_yaml::Value::deserialize(document).map(|_| ()))
+            .map_err(|error| error.to_string()),
+        Format::Json => serde_json::from_str::<serde_json::Value>(value)
+            .map(|_| ())
+            .map_err(|error| error.to_string()),
+        Format::Toml => value
+            .parse::<toml::Table>()
+            .map(|_| ())
+            .map_err(|error| error.message().to_string()),
+        Format::Properties => validate_properties(value),
+    }
+}
+
+/// Almost anything is a valid properties file: only `\u` escapes and a trailing `\` can be wrong
+fn validate_properties(value: &str) -> Result<(), String> {
+    let escape = regex(&PROPERTIES_ESCAPE, r"\\u([0-9a-fA-F]{4})?");
+    let mut continued = false;
+    for (index, line) in value.lines().enumerate() {
+        if !continued && is_comment(Format::Properties, line) {
+            continue;
+        }
+        if escape
+            .captures_iter(line)
+            .any(|captures| captures.get(1).is_none())
+        {
+            return Err(format!("invalid \\u escape at line {}", index + 1));
+        }
+        let backslashes = line.len() - line.trim_end_matches('\\').len();
+        continued = backslashes % 2 == 1;
+    }
+    match continued {
+        true => Err("line continuation at the end of the file".to_string()),
+        false => Ok(()),
+    }
+}
+
+fn is_comment(format: Format, line: &str) -> bool {
+    let line = line.trim_start();
+    match format {
+        Format::Properties => line.starts_with('#') || line.starts_with('!'),
+        Format::Yaml | Format::Toml => line.starts_with('#'),
+        Format::Json => false,
+    }
+}
+
+/// Lines of the value with keys, sections and comments coloured. Single-line JSON is indented
+pub fn highlight(format: Format, value: &str) -> Vec<String> {
+    let pretty = match format {
+        Format::Json if !value.trim().contains('\n') => {
+            serde_json::from_str::<serde_json::Value>(value)
+                .ok()
+                .and_then(|json| serde_json::to_string_pretty(&json).ok())
+        }
+        _ => None,
+    };
+    let key = match format {
+        Format::Yaml => regex(
+            &YAML_KEY,
+            r#"^(\s*(?:-\s+)?)([^\s#:'"-][^:#]*|"[^"]*"|'[^']*')(:(?:\s|$).*)$"#,
+        ),
+        Format::Json => regex(&JSON_KEY, r#"^(\s*)("(?:[^"\\]|\\.)*")(\s*:.*)$"#),
+        Format::Properties => regex(
+            &PROPERTIES_KEY,
+            r"^(\s*)((?:[^\\=:\s]|\\.)+)(\s*[=:\s].*)?$",
+        ),
+        Format::Toml => regex(&TOML_KEY, r#"^(\s*)([\w.-]+|"[^"]*")(\s*=.*)$"#),
+    };
+
+    pretty
+        .as_deref()
+        .unwrap_or(value)
+        .lines()
+        .map(|line| {
+            if is_comment(format, line) {
+                return line.dimmed().to_string();
+            }
+            if format == Format::Toml && line.trim_start().starts_with('[') {
+                return line.yellow().bold().to_string();
+            }
+            match key.captures(line) {
+                Some(captures) => format!(
+                    "{}{}{}",
+                    &captures[1],
+                    captures[2].cyan(),
+                    captures.get(3).map_or("", |rest| rest.as_str())
+                ),
+                None => line.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Guessed from the first bytes, like `file` does
+pub fn binary_type(bytes: &[u8]) -> &'static str {
+    const MAGIC: &[(&[u8], &str)] = &[
+        (b"\x1f\x8b", "gzip"),
+        (b"PK\x03\x04", "zip/jar"),
+        (b"\x89PNG\r\n\x1a\n", "png"),
+        (b"\xff\xd8\xff", "jpeg"),
+        (b"GIF8", "gif"),
+        (b"%PDF", "pdf"),
+        (b"\x7fELF", "elf"),
+        (b"BZh", "bzip2"),
+        (b"\x28\xb5\x2f\xfd", "zstd"),
+        (b"\xfe\xed\xfe\xed", "java keystore"),
+        (b"\x00asm", "wasm"),
+    ];
+    let tar = bytes.get(257..262) == Some(b"ustar");
+    match MAGIC.iter().find(|(magic, _)| bytes.starts_with(magic)) {
+        Some((_, kind)) => kind,
+        None if tar => "tar",
+        // DER: a SEQUENCE with a long form length, certificates and PKCS#12 keystores
+        None if bytes.starts_with(&[0x30, 0x82]) => "der",
+        None if std::str::from_utf8(bytes).is_ok() => "text",
+        None => "unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_by_extension_or_content() {
+        assert_eq!(detect("application.yml", "a: 1"), Some(Format::Yaml));
+        assert_eq!(detect("Cargo.TOML", "x"), Some(Format::Toml));
+        assert_eq!(detect("log4j.properties", "x"), Some(Format::Properties));
+        assert_eq!(detect("settings", r#"{"a": [1, 2]}"#), Some(Format::Json));
+        assert_eq!(
+            detect("config", "server:\n  port: 8080\n"),
+            Some(Format::Yaml)
+        );
+        assert_eq!(
+            detect("config", "[server]\nport = 8080\n"),
+            Some(Format::Toml)
+        );
+        assert_eq!(
+            detect("config", "# db\ndb.url=jdbc:postgresql://db\ndb.pool=10\n"),
+            Some(Format::Properties)
+        );
+        assert_eq!(detect("LOG_LEVEL", "debug"), None);
+        assert_eq!(detect("motd", "hello\nworld\n"), None);
+    }
+
+    #[test]
+    fn invalid_values_are_reported() {
+        assert!(validate(Format::Yaml, "a: 1\n---\nb: 2\n").is_ok());
+        assert!(validate(Format::Yaml, "a: [1, 2\n").is_err());
+        assert!(validate(Format::Json, r#"{"a": 1,}"#).is_err());
+        assert!(validate(Format::Toml, "port = 8080\n").is_ok());
+        assert!(validate(Format::Toml, "port = \n").is_err());
+        assert!(validate(Format::Properties, "a=1\nb=two \\\n  lines\n").is_ok());
+        assert_eq!(
+            validate(Format::Properties, "a=\\u00e9\nb=\\u0g\n"),
+            Err("invalid \\u escape at line 2".to_string())
+        );
+        assert!(validate(Format::Properties, "a=1\\\n").is_err());
+    }
+
+    /// Colours depend on the terminal and on the global override other tests may set
+    fn plain(lines: Vec<String>) -> Vec<String> {
+        let ansi = Regex::new(r"\x1b\[[0-9;]*m").unwrap();
+        lines
+            .iter()
+            .map(|line| ansi.replace_all(line, "").into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn one_line_json_is_indented() {
+        assert_eq!(
+            plain(highlight(Format::Json, r#"{"a":{"b":1}}"#)),
+            vec!["{", "  \"a\": {", "    \"b\": 1", "  }", "}"]
+        );
+        assert_eq!(
+            plain(highlight(Format::Yaml, "# comment\nserver:\n  port: 8080")),
+            vec!["# comment", "server:", "  port: 8080"]
+        );
+    }
+
+    #[test]
+    fn binary_types() {
+        assert_eq!(binary_type(b"\x1f\x8b\x08\x00"), "gzip");
+        assert_eq!(binary_type(b"PK\x03\x04rest"), "zip/jar");
+        assert_eq!(binary_type(&[0x30, 0x82, 0x01, 0x0a]), "der");
+        assert_eq!(binary_type(&[0xff, 0x00, 0x01]), "unknown");
+    }
+}
@@ -1,102 +1,524 @@
-use crate::cli_args::NamespaceArgs;
+pub mod cost;
+mod hpa;
+mod lint;
+mod recommend;
+mod totals;
+
+use crate::cli_args::{CpuMemArgs, SortBy};
+use crate::metrics::PodMetrics;
+use crate::quantity::{CpuMemory, Units};
 use crate::{get_k8s_env, Result};
-use k8s_openapi::api::core::v1::{Pod, ResourceRequirements};
-use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use colored::Colorize;
+use k8s_openapi::api::core::v1::Pod;
 use kube::api::ListParams;
 use kube::{api::Api, Client};
-use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::fmt::Display;
 use std::vec;
 
 #[derive(Debug)]
 struct PrintArguments {
     pod: String,
+    container: String,
     limit_cpu: String,
     limit_memory: String,
     request_cpu: String,
     request_memory: String,
+    usage_cpu: String,
+    usage_memory: String,
+    cpu_request_percent: String,
+    cpu_limit_percent: String,
+    memory_request_percent: String,
+    memory_limit_percent: String,
 }
 
 impl Display for PrintArguments {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{0: <100} {1: <20} {2: <20} {3: <20} {4: <20}",
-            self.pod, self.limit_cpu, self.limit_memory, self.request_cpu, self.request_memory
+            "{0: <70} {1: <40} {2: <20} {3: <20} {4: <20} {5: <20} {6: <20} {7: <20} {8} {9} {10} {11}",
+            self.pod,
+            self.container,
+            self.limit_cpu,
+            self.limit_memory,
+            self.request_cpu,
+            self.request_memory,
+            self.usage_cpu,
+            self.usage_memory,
+            self.cpu_request_percent,
+            self.cpu_limit_percent,
+            self.memory_request_percent,
+            self.memory_limit_percent
+        )
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct ContainerResources {
+    name: String,
+    init: bool,
+    limits: CpuMemory,
+    requests: CpuMemory,
+    usage: Option<CpuMemory>,
+}
+
+#[derive(Debug, PartialEq)]
+struct PodResources {
+    pod: String,
+    containers: Vec<ContainerResources>,
+}
+
+impl PodResources {
+    /// Sum of the app containers, init containers do not run alongside them
+    fn total(&self) -> ContainerResources {
+        self.containers.iter().filter(|c| !c.init).fold(
+            ContainerResources {
+                name: "total".to_string(),
+                init: false,
+                limits: CpuMemory::default(),
+                requests: CpuMemory::default(),
+                usage: None,
+            },
+            |total, c| ContainerResources {
+                limits: total.limits + c.limits,
+                requests: total.requests + c.requests,
+                usage: match (total.usage, c.usage) {
+                    (Some(total), Some(usage)) => Some(total + usage),
+                    (total, usage) => total.or(usage),
+                },
+                ..total
+            },
         )
     }
 }
 
+impl ContainerResources {
+    /// Neither requests nor limits: the container runs as BestEffort
+    fn unbounded(&self) -> bool {
+        self.requests == CpuMemory::default() && self.limits == CpuMemory::default()
+    }
+
+    fn sort_key(&self, sort_by: SortBy) -> Option<f64> {
+        match sort_by {
+            SortBy::Pod => None,
+            SortBy::LimitCpu => self.limits.cpu,
+            SortBy::LimitMemory => self.limits.memory,
+            SortBy::RequestCpu => self.requests.cpu,
+            SortBy::RequestMemory => self.requests.memory,
+            SortBy::UsageCpu => self.usage.and_then(|usage| usage.cpu),
+            SortBy::UsageMemory => self.usage.and_then(|usage| usage.memory),
+        }
+    }
+}
+
+/// Colours usage percentages once they get close to the request or the limit
+struct Thresholds {
+    warn: f64,
+    critical: f64,
+}
+
+impl Thresholds {
+    fn percent(&self, usage: Option<f64>, total: Option<f64>) -> String {
+        match (usage, total) {
+            (Some(usage), Some(total)) if total > 0.0 => {
+                let percent = usage / total * 100.0;
+                let text = format!("{: <15}", format!("{:.0}%", percent));
+                if percent >= self.critical {
+                    text.red().to_string()
+                } else if percent >= self.warn {
+                    text.yellow().to_string()
+                } else {
+                    text.green().to_string()
+                }
+            }
+            _ => format!("{: <15}", "N/A"),
+        }
+    }
+}
+
 #[tokio::main]
-pub async fn process(NamespaceArgs { namespace }: NamespaceArgs) -> Result<()> {
+pub async fn process(
+    CpuMemArgs {
+        namespace,
+        warn_percent,
+        critical_percent,
+        units,
+        sort_by,
+        totals,
+        init_containers,
+        lint,
+        lint_config,
+        recommend,
+        window,
+        interval,
+        request_headroom,
+        limit_headroom,
+        hpa,
+    }: CpuMemArgs,
+) -> Result<()> {
     let context = get_k8s_env()?;
     let client = Client::try_default().await?;
-    let api: Api<Pod> = Api::namespaced(client, namespace.as_str());
+    let thresholds = Thresholds {
+        warn: warn_percent,
+        critical: critical_percent,
+    };
 
     let headers = PrintArguments {
         pod: "pod".to_string(),
+        container: "container".to_string(),
         limit_cpu: "limit.cpu".to_string(),
         limit_memory: "limit.memory".to_string(),
         request_cpu: "request.cpu".to_string(),
         request_memory: "request.memory".to_string(),
+        usage_cpu: "usage.cpu".to_string(),
+        usage_memory: "usage.memory".to_string(),
+        cpu_request_percent: format!("{: <15}", "cpu/request"),
+        cpu_limit_percent: format!("{: <15}", "cpu/limit"),
+        memory_request_percent: format!("{: <15}", "memory/request"),
+        memory_limit_percent: format!("{: <15}", "memory/limit"),
     };
 
-    let infos: Vec<Option<PrintArguments>> = api
-        .list(&ListParams::default())
-        .await?
-        .into_iter()
-        .map(|p| collect_info(p))
-        .collect();
+    if lint {
+        let rules = lint::LintRules::load(lint_config.as_deref())?;
+        // QoS classes depend on the init containers as well
+        let pods = collect(client, namespace.as_str(), true).await?;
+        println!("ENVIRONMENT: {context}");
+        return lint::process(&pods, &rules, &units);
+    }
+
+    if recommend {
+        let headroom = recommend::Headroom {
+            request: request_headroom,
+            limit: limit_headroom,
+        };
+        println!("# ENVIRONMENT: {context}");
+        return recommend::process(
+            client,
+            namespace.as_str(),
+            window,
+            interval,
+            &headroom,
+            &units,
+        )
+        .await;
+    }
 
-    let mut results: Vec<Option<PrintArguments>> = vec![Some(headers)];
+    let mut pods = collect(client.clone(), namespace.as_str(), init_containers).await?;
+    if let Some(sort_by) = sort_by {
+        sort(&mut pods, sort_by);
+    }
+    let infos = pods.iter().flat_map(|pod| {
+        let total = (pod.containers.len() > 1).then(|| pod.total());
+        pod.containers
+            .iter()
+            .chain(total.iter())
+            .map(|resources| to_print_arguments(&pod.pod, resources, &thresholds, &units))
+            .collect::<Vec<PrintArguments>>()
+    });
+
+    let mut results: Vec<PrintArguments> = vec![headers];
     results.extend(infos);
 
     print_result(context.as_str(), &results);
 
+    if totals {
+        totals::process(client.clone(), namespace.as_str(), &thresholds, &units).await?;
+    }
+    if hpa {
+        hpa::process(client, namespace.as_str()).await?;
+    }
+
     Ok(())
 }
 
-fn print_result(context: &str, info: &[Option<PrintArguments>]) {
+fn print_result(context: &str, info: &[PrintArguments]) {
     println!("ENVIRONMENT: {context}");
-    info.into_iter()
-        .flatten()
-        .for_each(|item| println!("{}", item))
+    info.iter().for_each(|item| println!("{}", item))
+}
+
+async fn collect(
+    client: Client,
+    namespace: &str,
+    init_containers: bool,
+) -> Result<Vec<PodResources>> {
+    let api: Api<Pod> = Api::namespaced(client.clone(), namespace);
+    let mut usage = collect_usage(client, namespace).await;
+
+    let infos = api
+        .list(&ListParams::default())
+        .await?
+        .into_iter()
+        .flat_map(|p| collect_info(p, init_containers))
+        .map(|mut pod| {
+            pod.containers.iter_mut().for_each(|container| {
+                let key = (pod.pod.to_string(), container.name.to_string());
+                container.usage = usage.remove(&key);
+            });
+            pod
+        })
+        .collect();
+    Ok(infos)
+}
+
+/// Pods by name, numeric columns (of the pod total) from the highest to the lowest with missing values last
+fn sort(pods: &mut [PodResources], sort_by: SortBy) {
+    match sort_by {
+        SortBy::Pod => pods.sort_by(|a, b| a.pod.cmp(&b.pod)),
+        _ => pods.sort_by(|a, b| {
+            let key = |p: &PodResources| p.total().sort_key(sort_by).unwrap_or(f64::NEG_INFINITY);
+            key(b).total_cmp(&key(a))
+        }),
+    }
 }
 
-fn collect_info(p: Pod) -> Option<PrintArguments> {
+/// Usage per (pod, container). Clusters without metrics-server only lose the usage columns
+async fn collect_usage(client: Client, namespace: &str) -> HashMap<(String, String), CpuMemory> {
+    let api: Api<PodMetrics> = Api::namespaced(client, namespace);
+    match api.list(&ListParams::default()).await {
+        Ok(metrics) => metrics
+            .into_iter()
+            .flat_map(|pod| {
+                let name = pod.metadata.name.unwrap_or_default();
+                pod.containers.into_iter().map(move |container| {
+                    let usage = CpuMemory::from_quantities(&container.usage);
+                    ((name.to_string(), container.name), usage)
+                })
+            })
+            .collect(),
+        Err(error) => {
+            eprintln!(
+                "{} metrics.k8s.io is not available, usage is not shown: {}",
+                "WARNING".yellow(),
+                error
+            );
+            HashMap::new()
+        }
+    }
+}
+
+fn collect_info(p: Pod, init_containers: bool) -> Option<PodResources> {
     let spec = p.spec?;
     let name = p.metadata.name?;
 
-    let containers: Vec<&ResourceRequirements> = spec
+    let init = spec
+        .init_containers
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|_| init_containers)
+        .map(|c| (c, true));
+    let containers = spec
         .containers
-        .iter()
-        .filter(|x| name.contains(&x.name))
-        .flat_map(|c| c.resources.as_ref())
+        .into_iter()
+        .map(|c| (c, false))
+        .chain(init)
+        .map(|(c, init)| {
+            let resources = c.resources.unwrap_or_default();
+            ContainerResources {
+                name: c.name,
+                init,
+                limits: CpuMemory::from_quantities(&resources.limits.unwrap_or_default()),
+                requests: CpuMemory::from_quantities(&resources.requests.unwrap_or_default()),
+                usage: None,
+            }
+        })
         .collect();
 
-    if let Some(resource) = containers.first() {
-        let limits = resource.limits.as_ref();
-        let requests = resource.requests.as_ref();
+    Some(PodResources {
+        pod: name,
+        containers,
+    })
+}
 
-        let cpu_mem = PrintArguments {
-            pod: name,
-            limit_cpu: extract_info(limits, "cpu"),
-            limit_memory: extract_info(limits, "memory"),
-            request_cpu: extract_info(requests, "cpu"),
-            request_memory: extract_info(requests, "memory"),
-        };
-        Some(cpu_mem)
+fn to_print_arguments(
+    pod: &str,
+    resources: &ContainerResources,
+    thresholds: &Thresholds,
+    units: &Units,
+) -> PrintArguments {
+    let limits = resources.limits;
+    let requests = resources.requests;
+    let usage = resources.usage.unwrap_or_default();
+
+    let name = if resources.init {
+        format!("init:{}", resources.name)
+    } else {
+        resources.name.to_string()
+    };
+    let container = if resources.unbounded() {
+        format!("{: <40}", format!("{} (no requests/limits)", name))
+            .red()
+            .to_string()
     } else {
-        None
+        name
+    };
+
+    PrintArguments {
+        pod: pod.to_string(),
+        container,
+        limit_cpu: units.cpu(limits.cpu),
+        limit_memory: units.memory(limits.memory),
+        request_cpu: units.cpu(requests.cpu),
+        request_memory: units.memory(requests.memory),
+        usage_cpu: units.cpu(usage.cpu),
+        usage_memory: units.memory(usage.memory),
+        cpu_request_percent: thresholds.percent(usage.cpu, requests.cpu),
+        cpu_limit_percent: thresholds.percent(usage.cpu, limits.cpu),
+        memory_request_percent: thresholds.percent(usage.memory, requests.memory),
+        memory_limit_percent: thresholds.percent(usage.memory, limits.memory),
     }
 }
 
-fn extract_info(btree: Option<&BTreeMap<String, Quantity>>, key: &str) -> String {
-    match btree {
-        Some(info) => info
-            .get(key)
-            .map(|x| x.0.to_string())
-            .unwrap_or("N/A".to_string()),
-        None => "N/A".to_string(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Minimal stand-in for the API server: answers GET requests by path, 404 otherwise
+    fn fake_api_server(routes: Vec<(&'static str, serde_json::Value)>) -> Client {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    header.clear();
+                }
+
+                let path = request_line.split(' ').nth(1).unwrap_or_default();
+                let path = path.split('?').next().unwrap_or_default();
+                let (status, body) = match routes.iter().find(|(route, _)| *route == path) {
+                    Some((_, body)) => ("200 OK", body.to_string()),
+                    None => (
+                        "404 Not Found",
+                        json!({"kind": "Status", "apiVersion": "v1", "status": "Failure",
+                               "message": "not found", "reason": "NotFound", "code": 404})
+                        .to_string(),
+                    ),
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+        Client::try_from(kube::Config::new(url.parse().unwrap())).unwrap()
+    }
+
+    fn pods() -> serde_json::Value {
+        json!({
+            "kind": "PodList", "apiVersion": "v1", "metadata": {},
+            "items": [{
+                "metadata": {"name": "billing-5d8f7c9b4-x2x7z", "namespace": "team"},
+                "spec": {"containers": [{
+                    "name": "billing",
+                    "resources": {
+                        "requests": {"cpu": "100m", "memory": "128Mi"},
+                        "limits": {"cpu": "200m", "memory": "256Mi"}
+                    }
+                }]}
+            }]
+        })
+    }
+
+    fn pod_metrics() -> serde_json::Value {
+        json!({
+            "kind": "PodMetricsList", "apiVersion": "metrics.k8s.io/v1beta1", "metadata": {},
+            "items": [{
+                "metadata": {"name": "billing-5d8f7c9b4-x2x7z", "namespace": "team"},
+                "timestamp": "2023-03-01T10:00:00Z",
+                "window": "30s",
+                "containers": [{"name": "billing", "usage": {"cpu": "50m", "memory": "192Mi"}}]
+            }]
+        })
+    }
+
+    #[tokio::test]
+    async fn usage_is_read_from_pod_metrics() {
+        let client = fake_api_server(vec![
+            ("/api/v1/namespaces/team/pods", pods()),
+            (
+                "/apis/metrics.k8s.io/v1beta1/namespaces/team/pods",
+                pod_metrics(),
+            ),
+        ]);
+
+        let pods = collect(client, "team", false).await.unwrap();
+
+        assert_eq!(pods.len(), 1);
+        let usage = pods[0].containers[0].usage.unwrap();
+        assert_eq!(usage.cpu, Some(50.0));
+        assert_eq!(usage.memory, Some(192.0 * 1024.0 * 1024.0));
+    }
+
+    #[tokio::test]
+    async fn missing_metrics_api_only_drops_usage() {
+        let client = fake_api_server(vec![("/api/v1/namespaces/team/pods", pods())]);
+
+        let pods = collect(client, "team", false).await.unwrap();
+
+        assert_eq!(pods.len(), 1);
+        assert_eq!(pods[0].containers[0].usage, None);
+        assert_eq!(pods[0].containers[0].requests.cpu, Some(100.0));
+    }
+
+    #[test]
+    fn every_container_is_listed_with_a_pod_total() {
+        let pod: Pod = serde_json::from_value(json!({
+            "metadata": {"name": "kafka-0"},
+            "spec": {
+                "initContainers": [{"name": "migrate", "resources": {"requests": {"cpu": "1"}}}],
+                "containers": [
+                    {"name": "broker", "resources": {"requests": {"cpu": "500m", "memory": "1Gi"}}},
+                    {"name": "istio-proxy", "resources": {"requests": {"cpu": "100m"}, "limits": {"memory": "128Mi"}}},
+                    {"name": "exporter"}
+                ]
+            }
+        }))
+        .unwrap();
+
+        let without_init = collect_info(pod.clone(), false).unwrap();
+        let names: Vec<&str> = without_init
+            .containers
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["broker", "istio-proxy", "exporter"]);
+        assert!(without_init.containers[2].unbounded());
+        assert!(!without_init.containers[1].unbounded());
+
+        let with_init = collect_info(pod, true).unwrap();
+        assert_eq!(with_init.containers.len(), 4);
+        assert!(with_init.containers[3].init);
+
+        let total = with_init.total();
+        assert_eq!(total.requests.cpu, Some(600.0));
+        assert_eq!(total.requests.memory, Some(1024.0 * 1024.0 * 1024.0));
+        assert_eq!(total.limits.memory, Some(128.0 * 1024.0 * 1024.0));
+        assert_eq!(total.limits.cpu, None);
+    }
+
+    #[test]
+    fn usage_percent_of_request_and_limit() {
+        colored::control::set_override(false);
+        let thresholds = Thresholds {
+            warn: 70.0,
+            critical: 90.0,
+        };
+
+        assert_eq!(thresholds.percent(Some(50.0), Some(100.0)).trim(), "50%");
+        assert_eq!(thresholds.percent(Some(192.0), Some(128.0)).trim(), "150%");
+        assert_eq!(thresholds.percent(Some(50.0), None).trim(), "N/A");
+        assert_eq!(thresholds.percent(None, Some(100.0)).trim(), "N/A");
     }
 }
@@ -0,0 +1,338 @@
+use super::collect_usage;
+use super::recommend::workload_kind;
+use super::totals::{container_defaults, workload_totals};
+use crate::cli_args::CostArgs;
+use crate::quantity::CpuMemory;
+use crate::{get_k8s_env, k8s_client, Result};
+use colored::Colorize;
+use k8s_openapi::api::core::v1::{LimitRange, Pod};
+use kube::api::ListParams;
+use kube::{api::Api, Client};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+const HOURS_PER_MONTH: f64 = 730.0;
+const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+/// Prices, read from the `--prices` YAML file:
+///
+/// ```yaml
+/// cpu_core_hour: 0.031
+/// memory_gib_hour: 0.0042
+/// currency: USD
+/// ```
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct Prices {
+    cpu_core_hour: f64,
+    memory_gib_hour: f64,
+    #[serde(default)]
+    currency: String,
+}
+
+impl Prices {
+    fn load(path: &Path) -> Result<Self> {
+        Ok(serde_yaml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    fn monthly(&self, resources: CpuMemory) -> f64 {
+        let cores = resources.cpu.unwrap_or_default() / 1000.0;
+        let gib = resources.memory.unwrap_or_default() / GIB;
+        (cores * self.cpu_core_hour + gib * self.memory_gib_hour) * HOURS_PER_MONTH
+    }
+}
+
+/// A service, or the total of a namespace or a context
+#[derive(Debug, PartialEq)]
+struct Row {
+    scope: &'static str,
+    context: String,
+    namespace: String,
+    service: String,
+    replicas: i32,
+    resources: CpuMemory,
+    basis: &'static str,
+    cost: f64,
+}
+
+impl Row {
+    fn total(scope: &'static str, context: &str, namespace: &str) -> Row {
+        Row {
+            scope,
+            context: context.to_string(),
+            namespace: namespace.to_string(),
+            service: String::new(),
+            replicas: 0,
+            resources: CpuMemory::default(),
+            basis: "",
+            cost: 0.0,
+        }
+    }
+
+    fn add(&mut self, row: &Row) {
+        self.replicas += row.replicas;
+        self.resources = self.resources + row.resources;
+        self.cost += row.cost;
+    }
+}
+
+#[tokio::main]
+pub async fn process(
+    CostArgs {
+        namespaces,
+        contexts,
+        prices,
+        usage,
+        csv,
+    }: CostArgs,
+) -> Result<()> {
+    let prices = Prices::load(&prices)?;
+    let contexts = if contexts.is_empty() {
+        vec![None]
+    } else {
+        contexts.into_iter().map(Some).collect()
+    };
+
+    let mut services = vec![];
+    for context in contexts {
+        let name = match &context {
+            Some(context) => context.to_uppercase(),
+            None => get_k8s_env()?,
+        };
+        let client = k8s_client(context.as_deref()).await?;
+        for namespace in &namespaces {
+            services.extend(service_costs(client.clone(), &name, namespace, usage, &prices).await?);
+        }
+    }
+
+    let rows = with_totals(services);
+    if csv {
+        print_csv(&rows);
+    } else {
+        print_report(&rows, &prices.currency);
+    }
+    Ok(())
+}
+
+/// Monthly cost of each workload of `--totals` (Deployments, StatefulSets, DaemonSets, running
+/// Jobs and bare pods): requests times replicas, or the summed usage of its pods with `--usage`
+/// when metrics.k8s.io answers
+async fn service_costs(
+    client: Client,
+    context: &str,
+    namespace: &str,
+    usage: bool,
+    prices: &Prices,
+) -> Result<Vec<Row>> {
+    let limit_ranges: Vec<LimitRange> = Api::namespaced(client.clone(), namespace)
+        .list(&ListParams::default())
+        .await?
+        .items;
+    let defaults = container_defaults(&limit_ranges);
+    let workloads = workload_totals(client.clone(), namespace, &defaults).await?;
+
+    let usage_per_workload = if usage {
+        workload_usage(client, namespace).await?
+    } else {
+        HashMap::new()
+    };
+
+    Ok(workloads
+        .into_iter()
+        .map(|w| {
+            let service = format!("{}/{}", w.kind, w.name);
+            let (resources, basis) = match usage_per_workload.get(&service) {
+                Some(usage) => (*usage, "usage"),
+                None => (w.requests, "requests"),
+            };
+            Row {
+                scope: "service",
+                context: context.to_string(),
+                namespace: namespace.to_string(),
+                service,
+                replicas: w.replicas,
+                resources,
+                basis,
+                cost: prices.monthly(resources),
+            }
+        })
+        .collect())
+}
+
+/// Usage summed per `kind/name`, a Deployment and a StatefulSet can share a name
+async fn workload_usage(client: Client, namespace: &str) -> Result<HashMap<String, CpuMemory>> {
+    let usage = collect_usage(client.clone(), namespace).await;
+    if usage.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let workloads: HashMap<String, String> = Api::<Pod>::namespaced(client, namespace)
+        .list(&ListParams::default())
+        .await?
+        .iter()
+        .flat_map(|p| {
+            let (kind, name) = workload_kind(p);
+            Some((p.metadata.name.clone()?, format!("{}/{}", kind, name)))
+        })
+        .collect();
+
+    let mut per_workload: HashMap<String, CpuMemory> = HashMap::new();
+    for ((pod, _), usage) in usage {
+        if let Some(workload) = workloads.get(&pod) {
+            let total = per_workload.entry(workload.to_string()).or_default();
+            *total = *total + usage;
+        }
+    }
+    Ok(per_workload)
+}
+
+/// Services followed by their namespace total, each context ends with its total
+fn with_totals(services: Vec<Row>) -> Vec<Row> {
+    let mut grouped: BTreeMap<String, BTreeMap<String, Vec<Row>>> = BTreeMap::new();
+    for row in services {
+        grouped
+            .entry(row.context.to_string())
+            .or_default()
+            .entry(row.namespace.to_string())
+            .or_default()
+            .push(row);
+    }
+
+    let mut rows = vec![];
+    for (context, namespaces) in grouped {
+        let mut context_total = Row::total("context", &context, "");
+        for (namespace, services) in namespaces {
+            let mut namespace_total = Row::total("namespace", &context, &namespace);
+            for service in services {
+                namespace_total.add(&service);
+                rows.push(service);
+            }
+            context_total.add(&namespace_total);
+            rows.push(namespace_total);
+        }
+        rows.push(context_total);
+    }
+    rows
+}
+
+fn print_report(rows: &[Row], currency: &str) {
+    let mut context = "";
+    for row in rows {
+        if row.context != context {
+            context = &row.context;
+            println!("ENVIRONMENT: {context}");
+            println!(
+                "{0: <30} {1: <70} {2: <10} {3: <12} {4: <12} {5: <10} monthly",
+                "namespace", "service", "replicas", "cpu.cores", "memory.GiB", "basis"
+            );
+        }
+        let line = format!(
+            "{0: <30} {1: <70} {2: <10} {3: <12.2} {4: <12.2} {5: <10} {6:.2} {7}",
+            row.namespace,
+            row.service,
+            row.replicas,
+            row.resources.cpu.unwrap_or_default() / 1000.0,
+            row.resources.memory.unwrap_or_default() / GIB,
+            row.basis,
+            row.cost,
+            currency
+        );
+        match row.scope {
+            "service" => println!("{}", line),
+            "namespace" => println!("{}", line.bold()),
+            _ => println!("{}\n", line.bold().white()),
+        }
+    }
+}
+
+fn print_csv(rows: &[Row]) {
+    println!("scope,context,namespace,service,replicas,cpu_cores,memory_gib,basis,monthly_cost");
+    rows.iter().for_each(|row| println!("{}", csv_line(row)));
+}
+
+fn csv_line(row: &Row) -> String {
+    [
+        row.scope.to_string(),
+        csv_field(&row.context),
+        csv_field(&row.namespace),
+        csv_field(&row.service),
+        row.replicas.to_string(),
+        format!("{:.3}", row.resources.cpu.unwrap_or_default() / 1000.0),
+        format!("{:.3}", row.resources.memory.unwrap_or_default() / GIB),
+        row.basis.to_string(),
+        format!("{:.2}", row.cost),
+    ]
+    .join(",")
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(context: &str, namespace: &str, name: &str, cpu: f64, cost: f64) -> Row {
+        Row {
+            scope: "service",
+            context: context.to_string(),
+            namespace: namespace.to_string(),
+            service: format!("Deployment/{}", name),
+            replicas: 2,
+            resources: CpuMemory {
+                cpu: Some(cpu),
+                memory: Some(GIB),
+            },
+            basis: "requests",
+            cost,
+        }
+    }
+
+    #[test]
+    fn monthly_cost_from_prices() {
+        let prices: Prices =
+            serde_yaml::from_str("cpu_core_hour: 0.04\nmemory_gib_hour: 0.005\n").unwrap();
+        let resources = CpuMemory {
+            cpu: Some(1500.0),
+            memory: Some(4.0 * GIB),
+        };
+
+        assert_eq!(prices.currency, "");
+        assert!((prices.monthly(resources) - (1.5 * 0.04 + 4.0 * 0.005) * 730.0).abs() < 1e-9);
+        assert_eq!(prices.monthly(CpuMemory::default()), 0.0);
+    }
+
+    #[test]
+    fn totals_per_namespace_and_context() {
+        let rows = with_totals(vec![
+            service("PROD", "billing", "api", 500.0, 10.0),
+            service("PROD", "billing", "worker", 250.0, 5.0),
+            service("PROD", "search", "api", 1000.0, 20.0),
+        ]);
+
+        let lines: Vec<String> = rows.iter().map(csv_line).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "service,PROD,billing,Deployment/api,2,0.500,1.000,requests,10.00",
+                "service,PROD,billing,Deployment/worker,2,0.250,1.000,requests,5.00",
+                "namespace,PROD,billing,,4,0.750,2.000,,15.00",
+                "service,PROD,search,Deployment/api,2,1.000,1.000,requests,20.00",
+                "namespace,PROD,search,,2,1.000,1.000,,20.00",
+                "context,PROD,,,6,1.750,3.000,,35.00",
+            ]
+        );
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field("prod"), "prod");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
@@ -0,0 +1,281 @@
+use super::totals::{container_defaults, ContainerDefaults};
+use crate::quantity::cpu_millis;
+use crate::Result;
+use colored::Colorize;
+use k8s_openapi::api::apps::v1::{Deployment, StatefulSet};
+use k8s_openapi::api::autoscaling::v2::{
+    HorizontalPodAutoscaler, MetricSpec, MetricStatus, MetricTarget, MetricValueStatus,
+};
+use k8s_openapi::api::core::v1::{LimitRange, PodTemplateSpec};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use kube::api::ListParams;
+use kube::{api::Api, Client};
+use std::collections::{BTreeMap, HashMap};
+
+pub async fn process(client: Client, namespace: &str) -> Result<()> {
+    let hpas: Vec<HorizontalPodAutoscaler> = Api::namespaced(client.clone(), namespace)
+        .list(&ListParams::default())
+        .await?
+        .items;
+    let limit_ranges: Vec<LimitRange> = Api::namespaced(client.clone(), namespace)
+        .list(&ListParams::default())
+        .await?
+        .items;
+    let defaults = container_defaults(&limit_ranges);
+
+    let deployments = Api::<Deployment>::namespaced(client.clone(), namespace)
+        .list(&ListParams::default())
+        .await?
+        .into_iter()
+        .flat_map(|d| Some((("Deployment", d.metadata.name?), d.spec?.template)));
+    let statefulsets = Api::<StatefulSet>::namespaced(client, namespace)
+        .list(&ListParams::default())
+        .await?
+        .into_iter()
+        .flat_map(|s| Some((("StatefulSet", s.metadata.name?), s.spec?.template)));
+    let templates: HashMap<(&str, String), PodTemplateSpec> =
+        deployments.chain(statefulsets).collect();
+
+    println!();
+    println!("{}", "HPA".bold().white());
+    println!(
+        "{0: <40} {1: <50} {2: <5} {3: <5} {4: <8} {5: <8} {6: <40} {7: <20} flags",
+        "name",
+        "target",
+        "min",
+        "max",
+        "current",
+        "desired",
+        "metrics (current/target)",
+        "last scale"
+    );
+    for hpa in &hpas {
+        let Some(spec) = hpa.spec.as_ref() else {
+            continue;
+        };
+        let status = hpa.status.as_ref();
+        let target = &spec.scale_target_ref;
+        let current = status.and_then(|s| s.current_replicas);
+        let metrics = spec.metrics.clone().unwrap_or_default();
+        let current_metrics = status
+            .and_then(|s| s.current_metrics.clone())
+            .unwrap_or_default();
+
+        let mut flags = vec![];
+        if current.is_some_and(|current| current >= spec.max_replicas) {
+            flags.push("pinned at max".to_string());
+        }
+        let template = templates.get(&(target.kind.as_str(), target.name.to_string()));
+        if let Some(template) = template {
+            let missing = missing_cpu_requests(&metrics, template, &defaults);
+            if !missing.is_empty() {
+                flags.push(format!("no cpu request on {}", missing.join(", ")));
+            }
+        }
+
+        println!(
+            "{0: <40} {1: <50} {2: <5} {3: <5} {4: <8} {5: <8} {6: <40} {7: <20} {8}",
+            hpa.metadata.name.as_deref().unwrap_or("N/A"),
+            format!("{}/{}", target.kind, target.name),
+            spec.min_replicas.unwrap_or(1),
+            spec.max_replicas,
+            current
+                .map(|current| current.to_string())
+                .unwrap_or("N/A".to_string()),
+            status
+                .map(|s| s.desired_replicas.to_string())
+                .unwrap_or("N/A".to_string()),
+            metrics_text(&metrics, &current_metrics),
+            status
+                .and_then(|s| s.last_scale_time.as_ref())
+                .map(|time| time.0.format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or("N/A".to_string()),
+            flags.join(", ").red()
+        );
+    }
+    Ok(())
+}
+
+/// `cpu 45%/80%, requests_per_second 120/100`, metrics are matched by name
+fn metrics_text(metrics: &[MetricSpec], current_metrics: &[MetricStatus]) -> String {
+    let current: HashMap<String, &MetricValueStatus> =
+        current_metrics.iter().flat_map(status_metric).collect();
+    metrics
+        .iter()
+        .flat_map(spec_metric)
+        .map(|(name, target)| {
+            let current = current
+                .get(&name)
+                .map(|current| {
+                    if target.average_utilization.is_some() {
+                        value_text(current.average_utilization, None, None)
+                    } else if target.average_value.is_some() {
+                        value_text(None, current.average_value.as_ref(), None)
+                    } else {
+                        value_text(None, None, current.value.as_ref())
+                    }
+                })
+                .unwrap_or("<unknown>".to_string());
+            let target = value_text(
+                target.average_utilization,
+                target.average_value.as_ref(),
+                target.value.as_ref(),
+            );
+            format!("{} {}/{}", name, current, target)
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn value_text(
+    utilization: Option<i32>,
+    average_value: Option<&Quantity>,
+    value: Option<&Quantity>,
+) -> String {
+    match (utilization, average_value.or(value)) {
+        (Some(utilization), _) => format!("{}%", utilization),
+        (None, Some(value)) => value.0.to_string(),
+        (None, None) => "<unknown>".to_string(),
+    }
+}
+
+fn spec_metric(metric: &MetricSpec) -> Option<(String, &MetricTarget)> {
+    if let Some(resource) = &metric.resource {
+        return Some((resource.name.to_string(), &resource.target));
+    }
+    if let Some(resource) = &metric.container_resource {
+        let name = format!("{}/{}", resource.container, resource.name);
+        return Some((name, &resource.target));
+    }
+    if let Some(pods) = &metric.pods {
+        return Some((pods.metric.name.to_string(), &pods.target));
+    }
+    if let Some(object) = &metric.object {
+        return Some((object.metric.name.to_string(), &object.target));
+    }
+    metric
+        .external
+        .as_ref()
+        .map(|external| (external.metric.name.to_string(), &external.target))
+}
+
+fn status_metric(metric: &MetricStatus) -> Option<(String, &MetricValueStatus)> {
+    if let Some(resource) = &metric.resource {
+        return Some((resource.name.to_string(), &resource.current));
+    }
+    if let Some(resource) = &metric.container_resource {
+        let name = format!("{}/{}", resource.container, resource.name);
+        return Some((name, &resource.current));
+    }
+    if let Some(pods) = &metric.pods {
+        return Some((pods.metric.name.to_string(), &pods.current));
+    }
+    if let Some(object) = &metric.object {
+        return Some((object.metric.name.to_string(), &object.current));
+    }
+    metric
+        .external
+        .as_ref()
+        .map(|external| (external.metric.name.to_string(), &external.current))
+}
+
+/// Containers a cpu utilization target is computed from but that get no cpu request:
+/// not set, not derived from the limit and not defaulted by a LimitRange. The HPA cannot scale them
+fn missing_cpu_requests(
+    metrics: &[MetricSpec],
+    template: &PodTemplateSpec,
+    defaults: &ContainerDefaults,
+) -> Vec<String> {
+    if defaults.requests.cpu.is_some() {
+        return vec![];
+    }
+    let utilization = |target: &MetricTarget| target.average_utilization.is_some();
+    let pod_utilization = metrics.iter().any(|metric| {
+        metric
+            .resource
+            .as_ref()
+            .is_some_and(|r| r.name == "cpu" && utilization(&r.target))
+    });
+    let container_utilization: Vec<&str> = metrics
+        .iter()
+        .flat_map(|metric| metric.container_resource.as_ref())
+        .filter(|r| r.name == "cpu" && utilization(&r.target))
+        .map(|r| r.container.as_str())
+        .collect();
+
+    template
+        .spec
+        .iter()
+        .flat_map(|spec| spec.containers.iter())
+        .filter(|container| {
+            pod_utilization || container_utilization.contains(&container.name.as_str())
+        })
+        .filter(|container| {
+            let resources = container.resources.as_ref();
+            let cpu = |quantities: Option<&BTreeMap<String, Quantity>>| {
+                quantities
+                    .and_then(|quantities| quantities.get("cpu"))
+                    .and_then(cpu_millis)
+            };
+            cpu(resources.and_then(|r| r.requests.as_ref())).is_none()
+                && cpu(resources.and_then(|r| r.limits.as_ref())).is_none()
+        })
+        .map(|container| container.name.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn metrics(metrics: serde_json::Value) -> Vec<MetricSpec> {
+        serde_json::from_value(metrics).unwrap()
+    }
+
+    fn template() -> PodTemplateSpec {
+        serde_json::from_value(json!({ "spec": { "containers": [
+            {"name": "app", "resources": {"requests": {"memory": "256Mi"}}},
+            {"name": "sidecar", "resources": {"limits": {"cpu": "100m"}}}
+        ]}}))
+        .unwrap()
+    }
+
+    #[test]
+    fn cpu_utilization_needs_cpu_requests() {
+        let cpu = metrics(json!([{"type": "Resource", "resource": {
+            "name": "cpu", "target": {"type": "Utilization", "averageUtilization": 80}}}]));
+        let memory_value = metrics(json!([{"type": "Resource", "resource": {
+            "name": "memory", "target": {"type": "AverageValue", "averageValue": "1Gi"}}}]));
+
+        assert_eq!(
+            missing_cpu_requests(&cpu, &template(), &ContainerDefaults::default()),
+            vec!["app"]
+        );
+        assert!(
+            missing_cpu_requests(&memory_value, &template(), &ContainerDefaults::default())
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn metrics_show_current_and_target() {
+        let metrics = metrics(json!([
+            {"type": "Resource", "resource": {
+                "name": "cpu", "target": {"type": "Utilization", "averageUtilization": 80}}},
+            {"type": "Pods", "pods": {
+                "metric": {"name": "requests_per_second"},
+                "target": {"type": "AverageValue", "averageValue": "100"}}}
+        ]));
+        let current: Vec<MetricStatus> = serde_json::from_value(json!([
+            {"type": "Resource", "resource": {
+                "name": "cpu", "current": {"averageUtilization": 45, "averageValue": "90m"}}}
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            metrics_text(&metrics, &current),
+            "cpu 45%/80%, requests_per_second <unknown>/100"
+        );
+    }
+}
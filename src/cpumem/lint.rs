@@ -0,0 +1,294 @@
+use super::{ContainerResources, PodResources};
+use crate::errors::MacgyverCmdErrors;
+use crate::quantity::{CpuMemory, Units};
+use crate::Result;
+use colored::Colorize;
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+
+/// Resource policy, read from the `--lint-config` YAML file:
+///
+/// ```yaml
+/// require_memory_limit: true
+/// forbid_cpu_limit: true
+/// max_limit_request_ratio:
+///   memory: 2
+/// max_request:
+///   cpu: "2"
+///   memory: 4Gi
+/// ```
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct LintRules {
+    require_memory_limit: bool,
+    forbid_cpu_limit: bool,
+    max_limit_request_ratio: BTreeMap<String, f64>,
+    max_request: BTreeMap<String, Quantity>,
+}
+
+impl Default for LintRules {
+    fn default() -> Self {
+        Self {
+            require_memory_limit: true,
+            forbid_cpu_limit: false,
+            max_limit_request_ratio: BTreeMap::new(),
+            max_request: BTreeMap::new(),
+        }
+    }
+}
+
+impl LintRules {
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        match path {
+            Some(path) => Ok(serde_yaml::from_str(&fs::read_to_string(path)?)?),
+            None => Ok(LintRules::default()),
+        }
+    }
+
+    fn check(&self, container: &ContainerResources, units: &Units) -> Vec<String> {
+        let requests = container.requests;
+        let limits = container.limits;
+        let max_request = CpuMemory::from_quantities(&self.max_request);
+        let mut violations = vec![];
+
+        if self.require_memory_limit && limits.memory.is_none() {
+            violations.push("memory limit is missing".to_string());
+        }
+        if self.forbid_cpu_limit && limits.cpu.is_some() {
+            violations.push(format!(
+                "cpu limit {} is set but forbidden by policy",
+                units.cpu(limits.cpu)
+            ));
+        }
+
+        let ratios = [
+            ("cpu", requests.cpu, limits.cpu),
+            ("memory", requests.memory, limits.memory),
+        ];
+        for (resource, request, limit) in ratios {
+            let max = self.max_limit_request_ratio.get(resource);
+            if let (Some(max), Some(request), Some(limit)) = (max, request, limit) {
+                let ratio = limit / request;
+                if request > 0.0 && ratio > *max {
+                    violations.push(format!(
+                        "{} limit/request ratio {:.1} is above {}",
+                        resource, ratio, max
+                    ));
+                }
+            }
+        }
+
+        if let (Some(request), Some(max)) = (requests.cpu, max_request.cpu) {
+            if request > max {
+                violations.push(format!(
+                    "cpu request {} is above {}",
+                    units.cpu(Some(request)),
+                    units.cpu(Some(max))
+                ));
+            }
+        }
+        if let (Some(request), Some(max)) = (requests.memory, max_request.memory) {
+            if request > max {
+                violations.push(format!(
+                    "memory request {} is above {}",
+                    units.memory(Some(request)),
+                    units.memory(Some(max))
+                ));
+            }
+        }
+        violations
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum QosClass {
+    Guaranteed,
+    Burstable,
+    BestEffort,
+}
+
+impl Display for QosClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let qos = match self {
+            QosClass::Guaranteed => "Guaranteed",
+            QosClass::Burstable => "Burstable",
+            QosClass::BestEffort => "BestEffort",
+        };
+        write!(f, "{}", qos)
+    }
+}
+
+/// Same rules as the kubelet: Guaranteed when every container has cpu and memory limits equal
+/// to its requests, BestEffort when no container sets any, Burstable otherwise
+fn qos_class(pod: &PodResources) -> QosClass {
+    if pod.containers.iter().all(|c| c.unbounded()) {
+        return QosClass::BestEffort;
+    }
+    let guaranteed = pod.containers.iter().all(|c| {
+        let (requests, limits) = (c.requests, c.limits);
+        limits.cpu.is_some()
+            && limits.memory.is_some()
+            && (requests.cpu.is_none() || requests.cpu == limits.cpu)
+            && (requests.memory.is_none() || requests.memory == limits.memory)
+    });
+    if guaranteed {
+        QosClass::Guaranteed
+    } else {
+        QosClass::Burstable
+    }
+}
+
+/// Prints the QoS class and the violations of every pod, fails when there is at least one violation
+pub fn process(pods: &[PodResources], rules: &LintRules, units: &Units) -> Result<()> {
+    let mut violations = 0;
+
+    println!(
+        "{0: <70} {1: <12} {2: <40} violation",
+        "pod", "qos", "container"
+    );
+    for pod in pods {
+        let qos = qos_class(pod);
+        let mut pod_violations = 0;
+        for container in &pod.containers {
+            for violation in rules.check(container, units) {
+                pod_violations += 1;
+                println!(
+                    "{0: <70} {1: <12} {2: <40} {3}",
+                    pod.pod,
+                    qos,
+                    container.name,
+                    violation.red()
+                );
+            }
+        }
+        if pod_violations == 0 {
+            println!(
+                "{0: <70} {1: <12} {2: <40} {3}",
+                pod.pod,
+                qos,
+                "",
+                "OK".green()
+            );
+        }
+        violations += pod_violations;
+    }
+
+    if violations > 0 {
+        Err(MacgyverCmdErrors::PolicyViolations(violations))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn container(requests: CpuMemory, limits: CpuMemory) -> ContainerResources {
+        ContainerResources {
+            name: "app".to_string(),
+            init: false,
+            limits,
+            requests,
+            usage: None,
+        }
+    }
+
+    fn pod(containers: Vec<ContainerResources>) -> PodResources {
+        PodResources {
+            pod: "app-0".to_string(),
+            containers,
+        }
+    }
+
+    fn cpu_memory(cpu: Option<f64>, memory: Option<f64>) -> CpuMemory {
+        CpuMemory { cpu, memory }
+    }
+
+    #[test]
+    fn qos_classes() {
+        let none = CpuMemory::default();
+        let both = cpu_memory(Some(500.0), Some(1024.0));
+
+        assert_eq!(
+            qos_class(&pod(vec![container(none, none)])),
+            QosClass::BestEffort
+        );
+        assert_eq!(
+            qos_class(&pod(vec![container(both, both)])),
+            QosClass::Guaranteed
+        );
+        assert_eq!(
+            qos_class(&pod(vec![container(none, both)])),
+            QosClass::Guaranteed
+        );
+        assert_eq!(
+            qos_class(&pod(vec![container(
+                cpu_memory(Some(100.0), Some(1024.0)),
+                both
+            )])),
+            QosClass::Burstable
+        );
+        assert_eq!(
+            qos_class(&pod(vec![container(both, both), container(none, none)])),
+            QosClass::Burstable
+        );
+    }
+
+    #[test]
+    fn rules_from_yaml() {
+        let rules: LintRules = serde_yaml::from_str(
+            "forbid_cpu_limit: true\nmax_limit_request_ratio:\n  memory: 2\nmax_request:\n  cpu: \"2\"\n",
+        )
+        .unwrap();
+
+        assert!(rules.require_memory_limit);
+        assert!(rules.forbid_cpu_limit);
+        assert_eq!(rules.max_limit_request_ratio.get("memory"), Some(&2.0));
+        assert_eq!(
+            rules.max_request.get("cpu"),
+            Some(&Quantity("2".to_string()))
+        );
+    }
+
+    #[test]
+    fn violations_of_each_rule() {
+        let rules = LintRules {
+            require_memory_limit: true,
+            forbid_cpu_limit: true,
+            max_limit_request_ratio: BTreeMap::from([("memory".to_string(), 2.0)]),
+            max_request: BTreeMap::from([("cpu".to_string(), Quantity("2".to_string()))]),
+        };
+        let units = Units::default();
+        let mi = 1024.0 * 1024.0;
+
+        let compliant = container(
+            cpu_memory(Some(500.0), Some(256.0 * mi)),
+            cpu_memory(None, Some(512.0 * mi)),
+        );
+        assert!(rules.check(&compliant, &units).is_empty());
+
+        let violating = container(
+            cpu_memory(Some(4000.0), Some(128.0 * mi)),
+            cpu_memory(Some(4000.0), Some(512.0 * mi)),
+        );
+        assert_eq!(
+            rules.check(&violating, &units),
+            vec![
+                "cpu limit 4000m is set but forbidden by policy",
+                "memory limit/request ratio 4.0 is above 2",
+                "cpu request 4000m is above 2000m",
+            ]
+        );
+
+        let no_memory_limit = container(cpu_memory(Some(500.0), None), CpuMemory::default());
+        assert_eq!(
+            rules.check(&no_memory_limit, &units),
+            vec!["memory limit is missing"]
+        );
+    }
+}
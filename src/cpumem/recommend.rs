@@ -0,0 +1,323 @@
+use super::{collect_info, ContainerResources};
+use crate::metrics::PodMetrics;
+use crate::quantity::{CpuMemory, Units};
+use crate::Result;
+use colored::Colorize;
+use k8s_openapi::api::core::v1::Pod;
+use kube::api::ListParams;
+use kube::{api::Api, Client};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tokio::time;
+
+const MI: f64 = 1024.0 * 1024.0;
+/// Recommended cpu is rounded up to this many millicores
+const CPU_STEP: f64 = 5.0;
+
+/// Headroom, in percent, added on top of the observed usage
+pub struct Headroom {
+    pub request: f64,
+    pub limit: f64,
+}
+
+#[derive(Debug, PartialEq)]
+struct Observed {
+    p50: f64,
+    p95: f64,
+    max: f64,
+}
+
+/// Samples of a container across every replica of its workload
+#[derive(Default)]
+struct Samples {
+    cpu: Vec<f64>,
+    memory: Vec<f64>,
+}
+
+/// Polls PodMetrics for `window` and prints, per workload container, the observed usage and a
+/// Helm values patch: requests from p95, limits from max, both with headroom
+pub async fn process(
+    client: Client,
+    namespace: &str,
+    window: Duration,
+    interval: Duration,
+    headroom: &Headroom,
+    units: &Units,
+) -> Result<()> {
+    let pods: Vec<Pod> = Api::namespaced(client.clone(), namespace)
+        .list(&ListParams::default())
+        .await?
+        .items;
+    let workloads: HashMap<String, String> = pods
+        .iter()
+        .flat_map(|p| Some((p.metadata.name.clone()?, workload(p))))
+        .collect();
+    // Every replica runs the same spec, the first one gives the current requests/limits
+    let mut current: BTreeMap<(String, String), ContainerResources> = BTreeMap::new();
+    for pod in pods {
+        let workload = workload(&pod);
+        for container in collect_info(pod, false)
+            .into_iter()
+            .flat_map(|p| p.containers)
+        {
+            current
+                .entry((workload.to_string(), container.name.to_string()))
+                .or_insert(container);
+        }
+    }
+
+    let samples = sample(client, namespace, window, interval, &workloads).await?;
+
+    for ((workload, container), samples) in samples {
+        let count = samples.cpu.len().max(samples.memory.len());
+        let cpu = observe(samples.cpu);
+        let memory = observe(samples.memory);
+        let current = current.get(&(workload.to_string(), container.to_string()));
+
+        println!("---");
+        println!(
+            "# {}/{}: {} samples over {}s",
+            workload,
+            container,
+            count,
+            window.as_secs()
+        );
+        if let Some(cpu) = &cpu {
+            println!(
+                "# cpu    p50 {} p95 {} max {} (request {}, limit {})",
+                units.cpu(Some(cpu.p50)),
+                units.cpu(Some(cpu.p95)),
+                units.cpu(Some(cpu.max)),
+                units.cpu(current.and_then(|c| c.requests.cpu)),
+                units.cpu(current.and_then(|c| c.limits.cpu))
+            );
+        }
+        if let Some(memory) = &memory {
+            println!(
+                "# memory p50 {} p95 {} max {} (request {}, limit {})",
+                units.memory(Some(memory.p50)),
+                units.memory(Some(memory.p95)),
+                units.memory(Some(memory.max)),
+                units.memory(current.and_then(|c| c.requests.memory)),
+                units.memory(current.and_then(|c| c.limits.memory))
+            );
+        }
+        print!("{}", serde_yaml::to_string(&patch(cpu, memory, headroom))?);
+    }
+    Ok(())
+}
+
+async fn sample(
+    client: Client,
+    namespace: &str,
+    window: Duration,
+    interval: Duration,
+    workloads: &HashMap<String, String>,
+) -> Result<BTreeMap<(String, String), Samples>> {
+    let api: Api<PodMetrics> = Api::namespaced(client, namespace);
+    let mut samples: BTreeMap<(String, String), Samples> = BTreeMap::new();
+    // metrics-server refreshes every 15-60s, polling faster returns the same window again
+    let mut seen: HashSet<(String, String)> = HashSet::new();
+    let started = Instant::now();
+    let mut ticker = time::interval(interval);
+    let mut last_error = None;
+
+    loop {
+        ticker.tick().await;
+        // One failed poll only loses that tick, not the whole window
+        let pods = match api.list(&ListParams::default()).await {
+            Ok(pods) => pods.items,
+            Err(error) => {
+                eprintln!("{} metrics not sampled: {}", "WARNING".yellow(), error);
+                last_error = Some(error);
+                vec![]
+            }
+        };
+        for pod in pods {
+            let name = pod.metadata.name.unwrap_or_default();
+            let timestamp = pod.timestamp.unwrap_or_default();
+            if !seen.insert((name.to_string(), timestamp)) {
+                continue;
+            }
+            // Pods created while sampling fall back to their own name
+            let workload = workloads.get(&name).unwrap_or(&name);
+            for container in pod.containers {
+                let usage = CpuMemory::from_quantities(&container.usage);
+                let entry = samples
+                    .entry((workload.to_string(), container.name))
+                    .or_default();
+                entry.cpu.extend(usage.cpu);
+                entry.memory.extend(usage.memory);
+            }
+        }
+        eprintln!(
+            "{} {} metrics samples after {}s/{}s",
+            "SAMPLING".cyan(),
+            seen.len(),
+            started.elapsed().as_secs(),
+            window.as_secs()
+        );
+        if started.elapsed() + interval > window {
+            break;
+        }
+    }
+    match last_error {
+        Some(error) if samples.is_empty() => Err(error.into()),
+        _ => Ok(samples),
+    }
+}
+
+/// Pods of a Deployment are named after their ReplicaSet, `<deployment>-<hash>`
+pub(super) fn workload(pod: &Pod) -> String {
+    workload_kind(pod).1
+}
+
+/// Kind and name of the workload of the pod, `Pod` and its own name without controller
+pub(super) fn workload_kind(pod: &Pod) -> (String, String) {
+    let owner = pod
+        .metadata
+        .owner_references
+        .iter()
+        .flatten()
+        .find(|owner| owner.controller == Some(true));
+    match owner {
+        Some(owner) if owner.kind == "ReplicaSet" => (
+            "Deployment".to_string(),
+            owner
+                .name
+                .rsplit_once('-')
+                .map(|(deployment, _)| deployment.to_string())
+                .unwrap_or_else(|| owner.name.to_string()),
+        ),
+        Some(owner) => (owner.kind.to_string(), owner.name.to_string()),
+        None => (
+            "Pod".to_string(),
+            pod.metadata.name.clone().unwrap_or_default(),
+        ),
+    }
+}
+
+fn observe(mut values: Vec<f64>) -> Option<Observed> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    Some(Observed {
+        p50: percentile(&values, 50.0),
+        p95: percentile(&values, 95.0),
+        max: values[values.len() - 1],
+    })
+}
+
+/// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[f64], percent: f64) -> f64 {
+    let rank = (percent / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// `value` plus `percent` headroom, rounded up to a multiple of `step`
+fn with_headroom(value: f64, percent: f64, step: f64) -> f64 {
+    (value * (1.0 + percent / 100.0) / step).ceil().max(1.0) * step
+}
+
+type Patch = BTreeMap<&'static str, BTreeMap<&'static str, BTreeMap<&'static str, String>>>;
+
+/// `resources:` block of the Helm values, in the units Kubernetes expects
+fn patch(cpu: Option<Observed>, memory: Option<Observed>, headroom: &Headroom) -> Patch {
+    let mut requests = BTreeMap::new();
+    let mut limits = BTreeMap::new();
+    if let Some(cpu) = cpu {
+        let cpu_quantity = |value, percent| format!("{}m", with_headroom(value, percent, CPU_STEP));
+        requests.insert("cpu", cpu_quantity(cpu.p95, headroom.request));
+        limits.insert("cpu", cpu_quantity(cpu.max, headroom.limit));
+    }
+    if let Some(memory) = memory {
+        let memory_quantity =
+            |value, percent| format!("{}Mi", with_headroom(value, percent, MI) / MI);
+        requests.insert("memory", memory_quantity(memory.p95, headroom.request));
+        limits.insert("memory", memory_quantity(memory.max, headroom.limit));
+    }
+    BTreeMap::from([(
+        "resources",
+        BTreeMap::from([("requests", requests), ("limits", limits)]),
+    )])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn percentiles_use_nearest_rank() {
+        let values: Vec<f64> = (1..=20).map(f64::from).collect();
+        assert_eq!(
+            observe(values),
+            Some(Observed {
+                p50: 10.0,
+                p95: 19.0,
+                max: 20.0
+            })
+        );
+        assert_eq!(
+            observe(vec![7.0]),
+            Some(Observed {
+                p50: 7.0,
+                p95: 7.0,
+                max: 7.0
+            })
+        );
+        assert_eq!(observe(vec![]), None);
+    }
+
+    #[test]
+    fn patch_adds_headroom_and_rounds_up() {
+        let headroom = Headroom {
+            request: 20.0,
+            limit: 50.0,
+        };
+        let cpu = Observed {
+            p50: 40.0,
+            p95: 101.0,
+            max: 200.0,
+        };
+        let memory = Observed {
+            p50: 90.0 * MI,
+            p95: 100.0 * MI,
+            max: 130.0 * MI,
+        };
+
+        let yaml = serde_yaml::to_string(&patch(Some(cpu), Some(memory), &headroom)).unwrap();
+
+        assert_eq!(
+            yaml,
+            "resources:\n  limits:\n    cpu: 300m\n    memory: 195Mi\n  requests:\n    cpu: 125m\n    memory: 120Mi\n"
+        );
+    }
+
+    #[test]
+    fn deployment_pods_belong_to_the_deployment() {
+        let pod = |owner: serde_json::Value| -> Pod {
+            serde_json::from_value(json!({
+                "metadata": {"name": "billing-7d9f8c6b5-x2x9z", "ownerReferences": owner}
+            }))
+            .unwrap()
+        };
+        let owner = |kind: &str, name: &str| json!([{"apiVersion": "apps/v1", "kind": kind, "name": name, "uid": "1", "controller": true}]);
+
+        assert_eq!(
+            workload(&pod(owner("ReplicaSet", "billing-7d9f8c6b5"))),
+            "billing"
+        );
+        assert_eq!(workload(&pod(owner("StatefulSet", "billing"))), "billing");
+        assert_eq!(workload(&pod(json!([]))), "billing-7d9f8c6b5-x2x9z");
+        assert_eq!(
+            workload_kind(&pod(owner("StatefulSet", "billing"))),
+            ("StatefulSet".to_string(), "billing".to_string())
+        );
+        assert_eq!(
+            workload_kind(&pod(owner("ReplicaSet", "billing-7d9f8c6b5"))),
+            ("Deployment".to_string(), "billing".to_string())
+        );
+    }
+}
@@ -0,0 +1,454 @@
+use super::Thresholds;
+use crate::quantity::{cpu_millis, memory_bytes, parse, CpuMemory, Units};
+use crate::Result;
+use colored::Colorize;
+use k8s_openapi::api::apps::v1::{DaemonSet, Deployment, StatefulSet};
+use k8s_openapi::api::batch::v1::Job;
+use k8s_openapi::api::core::v1::{LimitRange, Pod, PodTemplateSpec, ResourceQuota};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use kube::api::ListParams;
+use kube::{api::Api, Client};
+use std::collections::BTreeMap;
+
+/// Requests/limits the LimitRanges of the namespace give to containers that do not set them
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct ContainerDefaults {
+    pub(super) requests: CpuMemory,
+    limits: CpuMemory,
+}
+
+#[derive(Debug, PartialEq)]
+pub(super) struct WorkloadTotal {
+    pub(super) kind: &'static str,
+    pub(super) name: String,
+    pub(super) replicas: i32,
+    pub(super) requests: CpuMemory,
+    pub(super) limits: CpuMemory,
+}
+
+pub async fn process(
+    client: Client,
+    namespace: &str,
+    thresholds: &Thresholds,
+    units: &Units,
+) -> Result<()> {
+    let limit_ranges: Vec<LimitRange> = Api::namespaced(client.clone(), namespace)
+        .list(&ListParams::default())
+        .await?
+        .items;
+    let quotas: Vec<ResourceQuota> = Api::namespaced(client.clone(), namespace)
+        .list(&ListParams::default())
+        .await?
+        .items;
+    let defaults = container_defaults(&limit_ranges);
+    let workloads = workload_totals(client, namespace, &defaults).await?;
+
+    print_workloads(namespace, &workloads, units);
+    print_limit_ranges(&limit_ranges, units);
+    print_quotas(&quotas, &workloads, thresholds, units);
+    Ok(())
+}
+
+/// Requests/limits of every workload of the namespace: Deployments, StatefulSets,
+/// DaemonSets (one replica per scheduled node), running Jobs (CronJobs included,
+/// through the Jobs they create) and pods without owner
+pub(super) async fn workload_totals(
+    client: Client,
+    namespace: &str,
+    defaults: &ContainerDefaults,
+) -> Result<Vec<WorkloadTotal>> {
+    let deployments = Api::<Deployment>::namespaced(client.clone(), namespace)
+        .list(&ListParams::default())
+        .await?
+        .into_iter()
+        .flat_map(|d| {
+            let spec = d.spec?;
+            let replicas = spec.replicas.unwrap_or(1);
+            Some(workload_total(
+                "Deployment",
+                d.metadata.name?,
+                replicas,
+                &spec.template,
+                defaults,
+            ))
+        });
+    let statefulsets = Api::<StatefulSet>::namespaced(client.clone(), namespace)
+        .list(&ListParams::default())
+        .await?
+        .into_iter()
+        .flat_map(|s| {
+            let spec = s.spec?;
+            let replicas = spec.replicas.unwrap_or(1);
+            Some(workload_total(
+                "StatefulSet",
+                s.metadata.name?,
+                replicas,
+                &spec.template,
+                defaults,
+            ))
+        });
+    let daemonsets = Api::<DaemonSet>::namespaced(client.clone(), namespace)
+        .list(&ListParams::default())
+        .await?
+        .into_iter()
+        .flat_map(|d| {
+            let replicas = d.status.map_or(0, |status| status.desired_number_scheduled);
+            Some(workload_total(
+                "DaemonSet",
+                d.metadata.name?,
+                replicas,
+                &d.spec?.template,
+                defaults,
+            ))
+        });
+    let jobs = Api::<Job>::namespaced(client.clone(), namespace)
+        .list(&ListParams::default())
+        .await?
+        .into_iter()
+        .flat_map(|j| {
+            let replicas = j.status.and_then(|status| status.active).unwrap_or(0);
+            if replicas == 0 {
+                return None;
+            }
+            Some(workload_total(
+                "Job",
+                j.metadata.name?,
+                replicas,
+                &j.spec?.template,
+                defaults,
+            ))
+        });
+    let pods = Api::<Pod>::namespaced(client, namespace)
+        .list(&ListParams::default())
+        .await?
+        .into_iter()
+        .filter(is_bare)
+        .flat_map(|p| {
+            let template = PodTemplateSpec {
+                metadata: None,
+                spec: p.spec,
+            };
+            Some(workload_total(
+                "Pod",
+                p.metadata.name?,
+                1,
+                &template,
+                defaults,
+            ))
+        });
+    Ok(deployments
+        .chain(statefulsets)
+        .chain(daemonsets)
+        .chain(jobs)
+        .chain(pods)
+        .collect())
+}
+
+/// Pods no controller accounts for, finished ones do not hold resources anymore
+fn is_bare(pod: &Pod) -> bool {
+    let owned = pod
+        .metadata
+        .owner_references
+        .as_ref()
+        .is_some_and(|owners| !owners.is_empty());
+    let finished = pod
+        .status
+        .as_ref()
+        .and_then(|status| status.phase.as_deref())
+        .is_some_and(|phase| phase == "Succeeded" || phase == "Failed");
+    !owned && !finished
+}
+
+pub(super) fn container_defaults(limit_ranges: &[LimitRange]) -> ContainerDefaults {
+    limit_ranges
+        .iter()
+        .flat_map(|limit_range| limit_range.spec.as_ref())
+        .flat_map(|spec| spec.limits.iter())
+        .filter(|item| item.type_ == "Container")
+        .fold(ContainerDefaults::default(), |defaults, item| {
+            let limits = quantities(item.default.as_ref());
+            // defaultRequest falls back to default when not set
+            let requests = or(quantities(item.default_request.as_ref()), limits);
+            ContainerDefaults {
+                requests: or(defaults.requests, requests),
+                limits: or(defaults.limits, limits),
+            }
+        })
+}
+
+/// Sum of the containers of the pod template, times the replica count.
+/// Like the API server, a missing request falls back to the limit, then to the LimitRange default
+fn workload_total(
+    kind: &'static str,
+    name: String,
+    replicas: i32,
+    template: &PodTemplateSpec,
+    defaults: &ContainerDefaults,
+) -> WorkloadTotal {
+    let (requests, limits) = template
+        .spec
+        .iter()
+        .flat_map(|spec| spec.containers.iter())
+        .map(|container| {
+            let resources = container.resources.as_ref();
+            let requests = quantities(resources.and_then(|r| r.requests.as_ref()));
+            let limits = quantities(resources.and_then(|r| r.limits.as_ref()));
+            (
+                or(or(requests, limits), defaults.requests),
+                or(limits, defaults.limits),
+            )
+        })
+        .fold(
+            (CpuMemory::default(), CpuMemory::default()),
+            |(total_requests, total_limits), (requests, limits)| {
+                (total_requests + requests, total_limits + limits)
+            },
+        );
+
+    WorkloadTotal {
+        kind,
+        name,
+        replicas,
+        requests: requests * replicas as f64,
+        limits: limits * replicas as f64,
+    }
+}
+
+fn quantities(quantities: Option<&BTreeMap<String, Quantity>>) -> CpuMemory {
+    quantities
+        .map(CpuMemory::from_quantities)
+        .unwrap_or_default()
+}
+
+fn or(value: CpuMemory, fallback: CpuMemory) -> CpuMemory {
+    CpuMemory {
+        cpu: value.cpu.or(fallback.cpu),
+        memory: value.memory.or(fallback.memory),
+    }
+}
+
+fn print_workloads(namespace: &str, workloads: &[WorkloadTotal], units: &Units) {
+    println!();
+    println!("{}", "WORKLOADS".bold().white());
+    println!(
+        "{0: <15} {1: <70} {2: <10} {3: <20} {4: <20} {5: <20} {6: <20}",
+        "kind", "name", "replicas", "request.cpu", "request.memory", "limit.cpu", "limit.memory"
+    );
+    workloads.iter().for_each(|w| {
+        println!(
+            "{0: <15} {1: <70} {2: <10} {3: <20} {4: <20} {5: <20} {6: <20}",
+            w.kind,
+            w.name,
+            w.replicas,
+            units.cpu(w.requests.cpu),
+            units.memory(w.requests.memory),
+            units.cpu(w.limits.cpu),
+            units.memory(w.limits.memory)
+        )
+    });
+
+    let (requests, limits) = namespace_total(workloads);
+    println!(
+        "{0: <15} {1: <70} {2: <10} {3: <20} {4: <20} {5: <20} {6: <20}",
+        "Namespace".bold(),
+        namespace.bold(),
+        workloads.iter().map(|w| w.replicas).sum::<i32>(),
+        units.cpu(requests.cpu),
+        units.memory(requests.memory),
+        units.cpu(limits.cpu),
+        units.memory(limits.memory)
+    );
+}
+
+fn namespace_total(workloads: &[WorkloadTotal]) -> (CpuMemory, CpuMemory) {
+    workloads.iter().fold(
+        (CpuMemory::default(), CpuMemory::default()),
+        |(requests, limits), w| (requests + w.requests, limits + w.limits),
+    )
+}
+
+fn print_limit_ranges(limit_ranges: &[LimitRange], units: &Units) {
+    limit_ranges.iter().for_each(|limit_range| {
+        let name = limit_range.metadata.name.as_deref().unwrap_or("N/A");
+        println!();
+        println!("{} {}", "LIMITRANGE".bold().white(), name);
+        println!(
+            "{0: <15} {1: <20} {2: <20} {3: <20} {4: <20}",
+            "type", "default.cpu", "default.memory", "defaultRequest.cpu", "defaultRequest.memory"
+        );
+        limit_range
+            .spec
+            .iter()
+            .flat_map(|spec| spec.limits.iter())
+            .for_each(|item| {
+                let limits = quantities(item.default.as_ref());
+                let requests = quantities(item.default_request.as_ref());
+                println!(
+                    "{0: <15} {1: <20} {2: <20} {3: <20} {4: <20}",
+                    item.type_,
+                    units.cpu(limits.cpu),
+                    units.memory(limits.memory),
+                    units.cpu(requests.cpu),
+                    units.memory(requests.memory)
+                )
+            });
+    });
+}
+
+/// Compares quota usage and what the workloads would take at their current replica counts
+fn print_quotas(
+    quotas: &[ResourceQuota],
+    workloads: &[WorkloadTotal],
+    thresholds: &Thresholds,
+    units: &Units,
+) {
+    let (requests, limits) = namespace_total(workloads);
+    let replicas = workloads.iter().map(|w| w.replicas).sum::<i32>() as f64;
+
+    quotas.iter().for_each(|quota| {
+        let name = quota.metadata.name.as_deref().unwrap_or("N/A");
+        let status = quota.status.as_ref();
+        let hard = status.and_then(|s| s.hard.clone()).unwrap_or_default();
+        let used = status.and_then(|s| s.used.clone()).unwrap_or_default();
+
+        println!();
+        println!("{} {}", "RESOURCEQUOTA".bold().white(), name);
+        println!(
+            "{0: <20} {1: <20} {2: <20} {3: <15} {4: <20} {5: <15}",
+            "resource", "used", "hard", "used/hard", "workloads", "workloads/hard"
+        );
+        hard.iter().for_each(|(resource, hard)| {
+            let (kind, workloads) = match resource.as_str() {
+                "cpu" | "requests.cpu" => (QuotaKind::Cpu, requests.cpu),
+                "limits.cpu" => (QuotaKind::Cpu, limits.cpu),
+                "memory" | "requests.memory" => (QuotaKind::Memory, requests.memory),
+                "limits.memory" => (QuotaKind::Memory, limits.memory),
+                "pods" => (QuotaKind::Count, Some(replicas)),
+                _ => (QuotaKind::Count, None),
+            };
+            let hard = kind.value(hard);
+            let used = used.get(resource).and_then(|used| kind.value(used));
+            println!(
+                "{0: <20} {1: <20} {2: <20} {3} {4: <20} {5}",
+                resource,
+                kind.format(units, used),
+                kind.format(units, hard),
+                thresholds.percent(used, hard),
+                kind.format(units, workloads),
+                thresholds.percent(workloads, hard)
+            )
+        });
+    });
+}
+
+enum QuotaKind {
+    Cpu,
+    Memory,
+    Count,
+}
+
+impl QuotaKind {
+    fn value(&self, quantity: &Quantity) -> Option<f64> {
+        match self {
+            QuotaKind::Cpu => cpu_millis(quantity),
+            QuotaKind::Memory => memory_bytes(quantity),
+            QuotaKind::Count => parse(&quantity.0),
+        }
+    }
+
+    fn format(&self, units: &Units, value: Option<f64>) -> String {
+        match self {
+            QuotaKind::Cpu => units.cpu(value),
+            QuotaKind::Memory => units.memory(value),
+            QuotaKind::Count => value
+                .map(|value| value.to_string())
+                .unwrap_or("N/A".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::LimitRangeSpec;
+    use serde_json::json;
+
+    fn template(containers: serde_json::Value) -> PodTemplateSpec {
+        serde_json::from_value(json!({ "spec": { "containers": containers } })).unwrap()
+    }
+
+    fn limit_range(limits: serde_json::Value) -> LimitRange {
+        LimitRange {
+            spec: Some(
+                serde_json::from_value::<LimitRangeSpec>(json!({ "limits": limits })).unwrap(),
+            ),
+            ..LimitRange::default()
+        }
+    }
+
+    #[test]
+    fn only_running_pods_without_owner_are_bare() {
+        let pod = |value: serde_json::Value| serde_json::from_value::<Pod>(value).unwrap();
+
+        assert!(is_bare(&pod(json!({
+            "metadata": {"name": "debug"},
+            "status": {"phase": "Running"}
+        }))));
+        assert!(!is_bare(&pod(json!({
+            "metadata": {"name": "app-6d4f9-x2k", "ownerReferences": [
+                {"apiVersion": "apps/v1", "kind": "ReplicaSet", "name": "app-6d4f9", "uid": "1"}
+            ]},
+            "status": {"phase": "Running"}
+        }))));
+        assert!(!is_bare(&pod(json!({
+            "metadata": {"name": "migration"},
+            "status": {"phase": "Succeeded"}
+        }))));
+    }
+
+    #[test]
+    fn workload_total_sums_containers_times_replicas() {
+        let template = template(json!([
+            {"name": "app", "resources": {
+                "requests": {"cpu": "250m", "memory": "256Mi"},
+                "limits": {"cpu": "1", "memory": "512Mi"}}},
+            {"name": "sidecar", "resources": {
+                "requests": {"cpu": "50m", "memory": "64Mi"},
+                "limits": {"cpu": "100m", "memory": "128Mi"}}}
+        ]));
+
+        let total = workload_total(
+            "Deployment",
+            "app".to_string(),
+            3,
+            &template,
+            &ContainerDefaults::default(),
+        );
+
+        assert_eq!(total.requests.cpu, Some(900.0));
+        assert_eq!(total.requests.memory, Some(3.0 * 320.0 * 1024.0 * 1024.0));
+        assert_eq!(total.limits.cpu, Some(3300.0));
+        assert_eq!(total.limits.memory, Some(3.0 * 640.0 * 1024.0 * 1024.0));
+    }
+
+    #[test]
+    fn missing_requests_fall_back_to_limits_then_limit_range() {
+        let defaults = container_defaults(&[limit_range(json!([
+            {"type": "Container",
+             "default": {"cpu": "500m", "memory": "256Mi"},
+             "defaultRequest": {"cpu": "100m"}}
+        ]))]);
+        let template = template(json!([
+            {"name": "only-limits", "resources": {"limits": {"cpu": "200m", "memory": "128Mi"}}},
+            {"name": "nothing"}
+        ]));
+
+        let total = workload_total("Deployment", "app".to_string(), 1, &template, &defaults);
+
+        // 200m from the limit + 100m defaultRequest, memory request falls back to the default limit
+        assert_eq!(total.requests.cpu, Some(300.0));
+        assert_eq!(total.requests.memory, Some(384.0 * 1024.0 * 1024.0));
+        assert_eq!(total.limits.cpu, Some(700.0));
+        assert_eq!(total.limits.memory, Some(384.0 * 1024.0 * 1024.0));
+    }
+}
@@ -0,0 +1,137 @@
+use crate::cli_args::DiffArgs;
+use crate::{get_k8s_env, k8s_client, Result};
+use colored::Colorize;
+use kube::Client;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyDiff {
+    /// Only in the compared environment
+    Added,
+    /// Only in the current environment
+    Removed,
+    Changed,
+    Identical,
+}
+
+/// Objects by name, their keys with a comparable form of each value
+pub struct Side<T> {
+    pub label: String,
+    pub objects: BTreeMap<String, BTreeMap<String, T>>,
+}
+
+/// Client and namespace of the environment given by `--diff-context`/`--diff-namespace`,
+/// `None` when not diffing
+pub async fn target(args: &DiffArgs, namespace: &str) -> Result<Option<(Client, String, String)>> {
+    if args.diff_context.is_none() && args.diff_namespace.is_none() {
+        return Ok(None);
+    }
+    let client = k8s_client(args.diff_context.as_deref()).await?;
+    let context = match &args.diff_context {
+        Some(context) => context.to_uppercase(),
+        None => get_k8s_env()?,
+    };
+    let namespace = args.diff_namespace.as_deref().unwrap_or(namespace);
+    Ok(Some((
+        client,
+        format!("{}/{}", context, namespace),
+        namespace.to_string(),
+    )))
+}
+
+pub fn compare<'a, T: PartialEq>(
+    left: &'a BTreeMap<String, T>,
+    right: &'a BTreeMap<String, T>,
+) -> Vec<(&'a str, KeyDiff)> {
+    let keys: BTreeSet<&String> = left.keys().chain(right.keys()).collect();
+    keys.into_iter()
+        .map(|key| {
+            let diff = match (left.get(key), right.get(key)) {
+                (Some(left), Some(right)) if left == right => KeyDiff::Identical,
+                (Some(_), Some(_)) => KeyDiff::Changed,
+                (Some(_), None) => KeyDiff::Removed,
+                _ => KeyDiff::Added,
+            };
+            (key.as_str(), diff)
+        })
+        .collect()
+}
+
+/// Objects missing on one side count all their keys as added/removed
+pub fn print<T: PartialEq>(left: &Side<T>, right: &Side<T>) {
+    println!("ENVIRONMENT: {} <-> {}", left.label, right.label);
+    let names: BTreeSet<&String> = left.objects.keys().chain(right.objects.keys()).collect();
+    let empty = BTreeMap::new();
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+
+    for name in names {
+        println!("{}", name.bold().white());
+        let left_keys = left.objects.get(name).unwrap_or(&empty);
+        let right_keys = right.objects.get(name).unwrap_or(&empty);
+        for (key, diff) in compare(left_keys, right_keys) {
+            let text = match diff {
+                KeyDiff::Added => format!("added (only in {})", right.label).cyan(),
+                KeyDiff::Removed => format!("removed (only in {})", left.label).red(),
+                KeyDiff::Changed => "changed".yellow(),
+                KeyDiff::Identical => "identical".green(),
+            };
+            println!("      {0:_<50} {1}", key, text);
+            *counts.entry(diff.label()).or_default() += 1;
+        }
+        println!();
+    }
+
+    let summary: Vec<String> = [
+        KeyDiff::Added,
+        KeyDiff::Removed,
+        KeyDiff::Changed,
+        KeyDiff::Identical,
+    ]
+    .iter()
+    .map(|diff| {
+        let label = diff.label();
+        format!("{} {}", counts.get(label).unwrap_or(&0), label)
+    })
+    .collect();
+    println!("{}", summary.join(", "));
+}
+
+impl KeyDiff {
+    fn label(&self) -> &'static str {
+        match self {
+            KeyDiff::Added => "added",
+            KeyDiff::Removed => "removed",
+            KeyDiff::Changed => "changed",
+            KeyDiff::Identical => "identical",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_added_removed_changed_or_identical() {
+        let left = BTreeMap::from([
+            ("DATABASE_URL".to_string(), "postgres://a"),
+            ("API_KEY".to_string(), "k1"),
+            ("OLD".to_string(), "x"),
+        ]);
+        let right = BTreeMap::from([
+            ("DATABASE_URL".to_string(), "postgres://b"),
+            ("API_KEY".to_string(), "k1"),
+            ("NEW".to_string(), "y"),
+        ]);
+
+        assert_eq!(
+            compare(&left, &right),
+            vec![
+                ("API_KEY", KeyDiff::Identical),
+                ("DATABASE_URL", KeyDiff::Changed),
+                ("NEW", KeyDiff::Added),
+                ("OLD", KeyDiff::Removed),
+            ]
+        );
+    }
+}
@@ -0,0 +1,921 @@
+use crate::access::can_list_secrets;
+use crate::cli_args::{EnvArgs, Workload, WorkloadKind};
+use crate::errors::MacgyverCmdErrors;
+use crate::quantity::parse;
+use crate::{get_k8s_env, Result};
+use colored::Colorize;
+use k8s_openapi::api::apps::v1::{
+    ControllerRevision, DaemonSet, Deployment, ReplicaSet, StatefulSet,
+};
+use k8s_openapi::api::core::v1::{
+    ConfigMap, Container, Pod, PodTemplateSpec, ResourceFieldSelector, Secret,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{LabelSelector, ObjectMeta};
+use kube::api::ListParams;
+use kube::{api::Api, Client};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// A configmap or secret referenced by the containers
+#[derive(Debug, PartialEq)]
+enum Lookup {
+    Found(BTreeMap<String, Vec<u8>>),
+    Missing,
+    Forbidden,
+}
+
+#[derive(Debug, Default)]
+struct Objects {
+    configmaps: BTreeMap<String, Lookup>,
+    secrets: BTreeMap<String, Lookup>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    Text(String),
+    /// Secret values are never printed
+    Masked(usize),
+    /// Only known once the pod runs: fields of a pod template, limits defaulting to the node allocatable
+    Runtime(String),
+    /// Optional reference to a missing object or key, or a key kubelet skips: the variable is not set
+    Unset(String),
+    /// Reference to a missing object or key, the container can not be created
+    Error(String),
+}
+
+/// Which pods belong to a workload, and to its current revision
+#[derive(Debug)]
+struct Owner {
+    /// Uids of the controllers of the pods: the ReplicaSets of a Deployment, the workload otherwise
+    controllers: Vec<String>,
+    current: Option<Revision>,
+}
+
+#[derive(Debug)]
+enum Revision {
+    /// Uid of the current ReplicaSet of a Deployment
+    Controller(String),
+    /// `controller-revision-hash` label of the pods of a StatefulSet or DaemonSet
+    Hash(String),
+}
+
+/// Annotation of a Deployment and its ReplicaSets with the rollout revision
+const REVISION_ANNOTATION: &str = "deployment.kubernetes.io/revision";
+const REVISION_HASH_LABEL: &str = "controller-revision-hash";
+
+#[derive(Debug, PartialEq)]
+struct Variable {
+    name: String,
+    value: Value,
+    source: String,
+    /// Set again by a later envFrom or env entry
+    overridden: bool,
+}
+
+#[tokio::main]
+pub async fn process(
+    EnvArgs {
+        namespace,
+        workload,
+        container,
+    }: EnvArgs,
+) -> Result<()> {
+    let context = get_k8s_env()?;
+    let client = Client::try_default().await?;
+
+    let (label, pod) = find_pod(client.clone(), &namespace, &workload).await?;
+    let spec = pod.spec.clone().unwrap_or_default();
+    let containers: Vec<&Container> = spec
+        .init_containers
+        .iter()
+        .flatten()
+        .chain(spec.containers.iter())
+        .filter(|c| container.is_none() || container.as_ref() == Some(&c.name))
+        .collect();
+    if containers.is_empty() {
+        return Err(MacgyverCmdErrors::CliParameterMissing(format!(
+            "No container {} in {}",
+            container.unwrap_or_default(),
+            label
+        )));
+    }
+    let objects = fetch(client, &namespace, &containers).await?;
+
+    println!("ENVIRONMENT: {context}");
+    let mut errors = 0;
+    for container in containers {
+        println!(
+            "{} container {}",
+            label.bold().white(),
+            container.name.bold()
+        );
+        for variable in resolve(&pod, container, &objects) {
+            if matches!(variable.value, Value::Error(_)) && !variable.overridden {
+                errors += 1;
+            }
+            print_variable(&variable);
+        }
+        println!();
+    }
+
+    match errors {
+        0 => Ok(()),
+        count => Err(MacgyverCmdErrors::DanglingReferences(count)),
+    }
+}
+
+/// The pod itself, a running pod of the current revision of the workload, or the pod
+/// template when none runs. Pods are listed with the matchLabels of the workload selector,
+/// then filtered on their controller
+async fn find_pod(client: Client, namespace: &str, workload: &Workload) -> Result<(String, Pod)> {
+    let name = workload.name.as_str();
+    let (selector, template, owner) = match workload.kind {
+        WorkloadKind::Pod => {
+            let pod = Api::<Pod>::namespaced(client, namespace).get(name).await?;
+            return Ok((format!("pod/{}", name), pod));
+        }
+        WorkloadKind::Deployment => {
+            let deployment = Api::<Deployment>::namespaced(client.clone(), namespace)
+                .get(name)
+                .await?;
+            let spec = deployment.spec.unwrap_or_default();
+            let owner =
+                deployment_owner(&client, namespace, &deployment.metadata, &spec.selector).await?;
+            (spec.selector, spec.template, owner)
+        }
+        WorkloadKind::StatefulSet => {
+            let statefulset = Api::<StatefulSet>::namespaced(client.clone(), namespace)
+                .get(name)
+                .await?;
+            let spec = statefulset.spec.unwrap_or_default();
+            let owner = Owner {
+                controllers: statefulset.metadata.uid.into_iter().collect(),
+                current: statefulset
+                    .status
+                    .and_then(|status| status.update_revision)
+                    .map(Revision::Hash),
+            };
+            (spec.selector, spec.template, owner)
+        }
+        WorkloadKind::DaemonSet => {
+            let daemonset = Api::<DaemonSet>::namespaced(client.clone(), namespace)
+                .get(name)
+                .await?;
+            let spec = daemonset.spec.unwrap_or_default();
+            let owner =
+                daemonset_owner(&client, namespace, &daemonset.metadata, &spec.selector).await?;
+            (spec.selector, spec.template, owner)
+        }
+    };
+    let label = format!("{}/{}", workload.kind.label(), name);
+
+    let pods = match label_selector(&selector) {
+        Some(selector) => {
+            Api::<Pod>::namespaced(client, namespace)
+                .list(&ListParams::default().labels(&selector))
+                .await?
+                .items
+        }
+        None => vec![],
+    };
+    let candidates = candidates(&pods, &owner);
+    match candidates.first() {
+        Some(pod) => {
+            let picked = match candidates.len() {
+                1 => String::new(),
+                count => format!(" (picked out of {} pods)", count),
+            };
+            Ok((
+                format!(
+                    "{} pod/{}{}",
+                    label,
+                    pod.metadata.name.as_deref().unwrap_or_default(),
+                    picked
+                ),
+                (*pod).clone(),
+            ))
+        }
+        None => Ok((
+            format!("{} (template, no pod)", label),
+            template_pod(template, namespace),
+        )),
+    }
+}
+
+/// The ReplicaSets of the Deployment, the current one has the revision of the Deployment
+async fn deployment_owner(
+    client: &Client,
+    namespace: &str,
+    deployment: &ObjectMeta,
+    selector: &LabelSelector,
+) -> Result<Owner> {
+    let replicasets = match label_selector(selector) {
+        Some(selector) => {
+            Api::<ReplicaSet>::namespaced(client.clone(), namespace)
+                .list(&ListParams::default().labels(&selector))
+                .await?
+                .items
+        }
+        None => vec![],
+    };
+    let revision = |meta: &ObjectMeta| {
+        meta.annotations
+            .as_ref()
+            .and_then(|annotations| annotations.get(REVISION_ANNOTATION))
+            .cloned()
+    };
+    let owned: Vec<&ObjectMeta> = replicasets
+        .iter()
+        .map(|replicaset| &replicaset.metadata)
+        .filter(|meta| controller_uid(meta) == deployment.uid.as_deref())
+        .collect();
+    let current = owned
+        .iter()
+        .find(|meta| revision(meta).is_some() && revision(meta) == revision(deployment))
+        .and_then(|meta| meta.uid.clone())
+        .map(Revision::Controller);
+    Ok(Owner {
+        controllers: owned.iter().flat_map(|meta| meta.uid.clone()).collect(),
+        current,
+    })
+}
+
+/// The DaemonSet itself, its current revision is the ControllerRevision with the highest number
+async fn daemonset_owner(
+    client: &Client,
+    namespace: &str,
+    daemonset: &ObjectMeta,
+    selector: &LabelSelector,
+) -> Result<Owner> {
+    let revisions = match label_selector(selector) {
+        Some(selector) => {
+            Api::<ControllerRevision>::namespaced(client.clone(), namespace)
+                .list(&ListParams::default().labels(&selector))
+                .await?
+                .items
+        }
+        None => vec![],
+    };
+    let current = revisions
+        .iter()
+        .filter(|revision| controller_uid(&revision.metadata) == daemonset.uid.as_deref())
+        .max_by_key(|revision| revision.revision)
+        .and_then(|revision| revision.metadata.labels.as_ref()?.get(REVISION_HASH_LABEL))
+        .cloned()
+        .map(Revision::Hash);
+    Ok(Owner {
+        controllers: daemonset.uid.iter().cloned().collect(),
+        current,
+    })
+}
+
+fn controller_uid(meta: &ObjectMeta) -> Option<&str> {
+    meta.owner_references
+        .iter()
+        .flatten()
+        .find(|owner| owner.controller == Some(true))
+        .map(|owner| owner.uid.as_str())
+}
+
+/// Pods controlled by the workload, only those of the current revision when there are any,
+/// running ones first
+fn candidates<'a>(pods: &'a [Pod], owner: &Owner) -> Vec<&'a Pod> {
+    let owned: Vec<&Pod> = pods
+        .iter()
+        .filter(|pod| {
+            controller_uid(&pod.metadata)
+                .is_some_and(|uid| owner.controllers.iter().any(|c| c == uid))
+        })
+        .collect();
+    let current: Vec<&Pod> = owned
+        .iter()
+        .copied()
+        .filter(|pod| match &owner.current {
+            Some(Revision::Controller(uid)) => controller_uid(&pod.metadata) == Some(uid),
+            Some(Revision::Hash(hash)) => {
+                pod.metadata
+                    .labels
+                    .as_ref()
+                    .and_then(|labels| labels.get(REVISION_HASH_LABEL))
+                    == Some(hash)
+            }
+            None => true,
+        })
+        .collect();
+    let mut candidates = match current.is_empty() {
+        true => owned,
+        false => current,
+    };
+    let running =
+        |pod: &Pod| pod.status.as_ref().and_then(|s| s.phase.as_deref()) == Some("Running");
+    candidates.sort_by_key(|pod| !running(pod));
+    candidates
+}
+
+fn label_selector(selector: &LabelSelector) -> Option<String> {
+    let labels: Vec<String> = selector
+        .match_labels
+        .iter()
+        .flatten()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    (!labels.is_empty()).then(|| labels.join(","))
+}
+
+fn template_pod(template: PodTemplateSpec, namespace: &str) -> Pod {
+    let mut metadata = template.metadata.unwrap_or_default();
+    metadata.namespace = Some(namespace.to_string());
+    Pod {
+        metadata,
+        spec: template.spec,
+        status: None,
+    }
+}
+
+/// Every configmap and secret referenced by the containers, a secret we may not read is not an error.
+/// Secrets are only read behind the same access review as `secret`
+async fn fetch(client: Client, namespace: &str, containers: &[&Container]) -> Result<Objects> {
+    let mut configmap_names = BTreeSet::new();
+    let mut secret_names = BTreeSet::new();
+    for container in containers {
+        for env_from in container.env_from.iter().flatten() {
+            if let Some(name) = env_from
+                .config_map_ref
+                .as_ref()
+                .and_then(|r| r.name.clone())
+            {
+                configmap_names.insert(name);
+            }
+            if let Some(name) = env_from.secret_ref.as_ref().and_then(|r| r.name.clone()) {
+                secret_names.insert(name);
+            }
+        }
+        for value_from in container.env.iter().flatten().flat_map(|e| &e.value_from) {
+            if let Some(name) = value_from
+                .config_map_key_ref
+                .as_ref()
+                .and_then(|r| r.name.clone())
+            {
+                configmap_names.insert(name);
+            }
+            if let Some(name) = value_from
+                .secret_key_ref
+                .as_ref()
+                .and_then(|r| r.name.clone())
+            {
+                secret_names.insert(name);
+            }
+        }
+    }
+
+    let mut objects = Objects::default();
+    let configmaps: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
+    for name in configmap_names {
+        let lookup = lookup(configmaps.get_opt(&name).await, |c: ConfigMap| {
+            c.data
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(key, value)| (key, value.into_bytes()))
+                .collect()
+        })?;
+        objects.configmaps.insert(name, lookup);
+    }
+    if !can_list_secrets(client.clone(), namespace).await? {
+        objects.secrets = secret_names
+            .into_iter()
+            .map(|name| (name, Lookup::Forbidden))
+            .collect();
+        return Ok(objects);
+    }
+    let secrets: Api<Secret> = Api::namespaced(client, namespace);
+    for name in secret_names {
+        let lookup = lookup(secrets.get_opt(&name).await, |s: Secret| {
+            s.data
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(key, value)| (key, value.0))
+                .collect()
+        })?;
+        objects.secrets.insert(name, lookup);
+    }
+    Ok(objects)
+}
+
+fn lookup<K>(
+    object: core::result::Result<Option<K>, kube::Error>,
+    values: impl Fn(K) -> BTreeMap<String, Vec<u8>>,
+) -> Result<Lookup> {
+    match object {
+        Ok(Some(object)) => Ok(Lookup::Found(values(object))),
+        Ok(None) => Ok(Lookup::Missing),
+        Err(kube::Error::Api(response)) if response.code == 403 => Ok(Lookup::Forbidden),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Same order as kubelet: envFrom sources, then env entries, a later variable overrides an earlier one
+fn resolve(pod: &Pod, container: &Container, objects: &Objects) -> Vec<Variable> {
+    let mut variables: Vec<Variable> = vec![];
+    // Values that `$(NAME)` expands to, and whether they come from a secret
+    let mut defined: HashMap<String, (String, bool)> = HashMap::new();
+
+    for env_from in container.env_from.iter().flatten() {
+        let prefix = env_from.prefix.as_deref().unwrap_or_default();
+        let (kind, name, optional, lookup) = match (&env_from.config_map_ref, &env_from.secret_ref)
+        {
+            (Some(r), _) => {
+                let name = r.name.clone().unwrap_or_default();
+                (
+                    "configmap",
+                    name.clone(),
+                    r.optional,
+                    objects.configmaps.get(&name),
+                )
+            }
+            (_, Some(r)) => {
+                let name = r.name.clone().unwrap_or_default();
+                (
+                    "secret",
+                    name.clone(),
+                    r.optional,
+                    objects.secrets.get(&name),
+                )
+            }
+            _ => continue,
+        };
+        let source = format!("envFrom {}/{}", kind, name);
+        match lookup {
+            Some(Lookup::Found(values)) => {
+                for (key, value) in values {
+                    let variable_name = format!("{}{}", prefix, key);
+                    if !is_variable_name(&variable_name) {
+                        let value = Value::Unset("skipped: not a valid variable name".to_string());
+                        variables.push(Variable {
+                            name: variable_name,
+                            value,
+                            source: source.clone(),
+                            overridden: false,
+                        });
+                        continue;
+                    }
+                    let secret = kind == "secret";
+                    let text = String::from_utf8_lossy(value).to_string();
+                    let value = match secret {
+                        true => Value::Masked(value.len()),
+                        false => Value::Text(text.clone()),
+                    };
+                    let variable = Variable {
+                        name: variable_name,
+                        value,
+                        source: source.clone(),
+                        overridden: false,
+                    };
+                    add(&mut variables, &mut defined, variable, Some((text, secret)));
+                }
+            }
+            lookup => variables.push(Variable {
+                name: format!("{}*", prefix),
+                value: missing(kind, &name, None, optional, lookup),
+                source,
+                overridden: false,
+            }),
+        }
+    }
+
+    for env in container.env.iter().flatten() {
+        let (value, source, text) = match &env.value_from {
+            None => {
+                let (text, secret) = expand(env.value.as_deref().unwrap_or_default(), &defined);
+                let value = match secret {
+                    true => Value::Masked(text.len()),
+                    false => Value::Text(text.clone()),
+                };
+                (value, "env".to_string(), Some((text, secret)))
+            }
+            Some(value_from) => {
+                if let Some(r) = &value_from.config_map_key_ref {
+                    let name = r.name.clone().unwrap_or_default();
+                    let lookup = objects.configmaps.get(&name);
+                    let source = format!("configmap/{} key {}", name, r.key);
+                    match key_value(lookup, &r.key) {
+                        Some(value) => {
+                            let text = String::from_utf8_lossy(value).to_string();
+                            (Value::Text(text.clone()), source, Some((text, false)))
+                        }
+                        None => {
+                            let value =
+                                missing("configmap", &name, Some(&r.key), r.optional, lookup);
+                            (value, source, None)
+                        }
+                    }
+                } else if let Some(r) = &value_from.secret_key_ref {
+                    let name = r.name.clone().unwrap_or_default();
+                    let lookup = objects.secrets.get(&name);
+                    let source = format!("secret/{} key {}", name, r.key);
+                    match key_value(lookup, &r.key) {
+                        Some(value) => {
+                            let text = String::from_utf8_lossy(value).to_string();
+                            (Value::Masked(value.len()), source, Some((text, true)))
+                        }
+                        None => {
+                            let value = missing("secret", &name, Some(&r.key), r.optional, lookup);
+                            (value, source, None)
+                        }
+                    }
+                } else if let Some(r) = &value_from.field_ref {
+                    let source = format!("field {}", r.field_path);
+                    match field(pod, &r.field_path) {
+                        Some(text) => (Value::Text(text.clone()), source, Some((text, false))),
+                        None => (
+                            Value::Runtime("<set when the pod runs>".to_string()),
+                            source,
+                            None,
+                        ),
+                    }
+                } else if let Some(r) = &value_from.resource_field_ref {
+                    let source = format!("resource {}", r.resource);
+                    let value = resource(pod, container, r);
+                    let text = match &value {
+                        Value::Text(text) => Some((text.clone(), false)),
+                        _ => None,
+                    };
+                    (value, source, text)
+                } else {
+                    continue;
+                }
+            }
+        };
+        let variable = Variable {
+            name: env.name.clone(),
+            value,
+            source,
+            overridden: false,
+        };
+        add(&mut variables, &mut defined, variable, text);
+    }
+    variables
+}
+
+fn add(
+    variables: &mut Vec<Variable>,
+    defined: &mut HashMap<String, (String, bool)>,
+    variable: Variable,
+    text: Option<(String, bool)>,
+) {
+    variables
+        .iter_mut()
+        .filter(|v| v.name == variable.name)
+        .for_each(|v| v.overridden = true);
+    match text {
+        Some(text) => defined.insert(variable.name.clone(), text),
+        None => defined.remove(&variable.name),
+    };
+    variables.push(variable);
+}
+
+fn key_value<'a>(lookup: Option<&'a Lookup>, key: &str) -> Option<&'a Vec<u8>> {
+    match lookup {
+        Some(Lookup::Found(values)) => values.get(key),
+        _ => None,
+    }
+}
+
+/// A missing object or key: kubelet skips it when the reference is optional, else fails the container
+fn missing(
+    kind: &str,
+    name: &str,
+    key: Option<&str>,
+    optional: Option<bool>,
+    lookup: Option<&Lookup>,
+) -> Value {
+    let what = match (lookup, key) {
+        (Some(Lookup::Forbidden), _) => {
+            return Value::Runtime(format!("<not allowed to read {}/{}>", kind, name))
+        }
+        (Some(Lookup::Found(_)), Some(key)) => format!("missing key {} in {}/{}", key, kind, name),
+        _ => format!("missing {}/{}", kind, name),
+    };
+    match optional {
+        Some(true) => Value::Unset(format!("not set, optional {}", what)),
+        _ => Value::Error(what),
+    }
+}
+
+/// `$(NAME)` is replaced by an already defined variable, `$$` escapes a `$`.
+/// Also tells whether a secret value was used
+fn expand(value: &str, defined: &HashMap<String, (String, bool)>) -> (String, bool) {
+    let mut expanded = String::new();
+    let mut secret = false;
+    let mut rest = value;
+    while let Some(index) = rest.find('$') {
+        expanded.push_str(&rest[..index]);
+        rest = &rest[index..];
+        if let Some(after) = rest.strip_prefix("$$") {
+            expanded.push('$');
+            rest = after;
+        } else if let Some((name, after)) = rest.strip_prefix("$(").and_then(|r| r.split_once(')'))
+        {
+            match defined.get(name) {
+                Some((value, from_secret)) => {
+                    expanded.push_str(value);
+                    secret |= from_secret;
+                }
+                None => expanded.push_str(&rest[..name.len() + 3]),
+            }
+            rest = after;
+        } else {
+            expanded.push('$');
+            rest = &rest[1..];
+        }
+    }
+    expanded.push_str(rest);
+    (expanded, secret)
+}
+
+/// Fields of the downward API, `None` when the pod does not have them (yet)
+fn field(pod: &Pod, path: &str) -> Option<String> {
+    let spec = pod.spec.as_ref();
+    let status = pod.status.as_ref();
+    match path {
+        "metadata.name" => pod.metadata.name.clone(),
+        "metadata.namespace" => pod.metadata.namespace.clone(),
+        "metadata.uid" => pod.metadata.uid.clone(),
+        "spec.nodeName" => spec.and_then(|s| s.node_name.clone()),
+        "spec.serviceAccountName" => spec.and_then(|s| s.service_account_name.clone()),
+        "status.hostIP" => status.and_then(|s| s.host_ip.clone()),
+        "status.podIP" => status.and_then(|s| s.pod_ip.clone()),
+        "status.podIPs" => status.and_then(|s| {
+            let ips: Vec<String> = s
+                .pod_ips
+                .iter()
+                .flatten()
+                .flat_map(|ip| ip.ip.clone())
+                .collect();
+            (!ips.is_empty()).then(|| ips.join(","))
+        }),
+        path => {
+            let (map, key) = path
+                .strip_prefix("metadata.labels['")
+                .map(|key| (&pod.metadata.labels, key))
+                .or_else(|| {
+                    path.strip_prefix("metadata.annotations['")
+                        .map(|key| (&pod.metadata.annotations, key))
+                })?;
+            let key = key.strip_suffix("']")?;
+            Some(map.as_ref()?.get(key).cloned().unwrap_or_default())
+        }
+    }
+}
+
+/// requests/limits of a container divided by the divisor and rounded up, like kubelet
+fn resource(pod: &Pod, container: &Container, selector: &ResourceFieldSelector) -> Value {
+    let target = selector
+        .container_name
+        .as_deref()
+        .filter(|name| !name.is_empty())
+        .unwrap_or(&container.name);
+    let spec = pod.spec.clone().unwrap_or_default();
+    let Some(target) = spec
+        .containers
+        .iter()
+        .chain(spec.init_containers.iter().flatten())
+        .find(|c| c.name == target)
+    else {
+        return Value::Error(format!("no container {}", target));
+    };
+    let Some((kind, name)) = selector.resource.split_once('.') else {
+        return Value::Error(format!("invalid resource {}", selector.resource));
+    };
+    let resources = target.resources.clone().unwrap_or_default();
+    let quantities = match kind {
+        "limits" => resources.limits,
+        "requests" => resources.requests,
+        _ => return Value::Error(format!("invalid resource {}", selector.resource)),
+    };
+    let divisor = selector
+        .divisor
+        .as_ref()
+        .and_then(|divisor| parse(&divisor.0))
+        .filter(|divisor| *divisor > 0.0)
+        .unwrap_or(1.0);
+    match (
+        kind,
+        quantities
+            .unwrap_or_default()
+            .get(name)
+            .and_then(|q| parse(&q.0)),
+    ) {
+        // Rounded first: 0.5 / 0.001 is not exactly 500 with floats
+        (_, Some(value)) => {
+            Value::Text((((value / divisor) * 1e6).round() / 1e6).ceil().to_string())
+        }
+        ("limits", None) => Value::Runtime("<node allocatable>".to_string()),
+        (_, None) => Value::Text("0".to_string()),
+    }
+}
+
+/// Names kubelet accepts for envFrom keys
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || "_.-".contains(c))
+        && chars.all(|c| c.is_ascii_alphanumeric() || "_.-".contains(c))
+}
+
+fn print_variable(variable: &Variable) {
+    let value = match &variable.value {
+        Value::Text(text) => text.replace('\n', "\\n").normal(),
+        Value::Masked(length) => format!("<masked, {} bytes>", length).dimmed(),
+        Value::Runtime(text) => text.cyan(),
+        Value::Unset(text) => text.yellow(),
+        Value::Error(text) => text.red(),
+    };
+    let line = format!(
+        "      {0:_<50} {1: <60} {2}",
+        variable.name,
+        value,
+        variable.source.dimmed()
+    );
+    if variable.overridden {
+        println!("{} {}", line.dimmed(), "(overridden)".dimmed());
+    } else {
+        println!("{}", line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn pod() -> Pod {
+        serde_json::from_value(json!({
+            "metadata": {"name": "billing-7d9f", "namespace": "billing", "labels": {"app": "billing"}},
+            "spec": {"containers": [{
+                "name": "app",
+                "resources": {"limits": {"memory": "512Mi"}, "requests": {"cpu": "500m"}},
+                "envFrom": [
+                    {"configMapRef": {"name": "app"}},
+                    {"secretRef": {"name": "db"}, "prefix": "DB_"},
+                    {"configMapRef": {"name": "feature-flags", "optional": true}}
+                ],
+                "env": [
+                    {"name": "LOG_LEVEL", "value": "info"},
+                    {"name": "URL", "value": "http://$(HOST):8080/$(UNKNOWN)/$$(HOST)"},
+                    {"name": "DSN", "value": "postgres://app:$(DB_PASSWORD)@db"},
+                    {"name": "REGION", "valueFrom": {"configMapKeyRef": {"name": "app", "key": "REGION"}}},
+                    {"name": "TOKEN", "valueFrom": {"secretKeyRef": {"name": "api", "key": "token"}}},
+                    {"name": "POD_NAME", "valueFrom": {"fieldRef": {"fieldPath": "metadata.name"}}},
+                    {"name": "APP", "valueFrom": {"fieldRef": {"fieldPath": "metadata.labels['app']"}}},
+                    {"name": "POD_IP", "valueFrom": {"fieldRef": {"fieldPath": "status.podIP"}}},
+                    {"name": "CPU", "valueFrom": {"resourceFieldRef": {"resource": "requests.cpu", "divisor": "1m"}}},
+                    {"name": "MEMORY", "valueFrom": {"resourceFieldRef": {"resource": "limits.memory", "divisor": "1Mi"}}},
+                    {"name": "CPU_LIMIT", "valueFrom": {"resourceFieldRef": {"resource": "limits.cpu"}}}
+                ]
+            }]}
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn pods_of_the_current_revision_running_first() {
+        let pod = |name: &str, owner: &str, hash: &str, phase: &str| -> Pod {
+            serde_json::from_value(json!({
+                "metadata": {
+                    "name": name,
+                    "labels": {"app": "billing", REVISION_HASH_LABEL: hash},
+                    "ownerReferences": [{
+                        "apiVersion": "apps/v1", "kind": "ReplicaSet", "name": owner,
+                        "uid": owner, "controller": true
+                    }]
+                },
+                "status": {"phase": phase}
+            }))
+            .unwrap()
+        };
+        let pods = vec![
+            pod("old-running", "rs-1", "1", "Running"),
+            pod("other-workload", "rs-other", "2", "Running"),
+            pod("new-pending", "rs-2", "2", "Pending"),
+            pod("new-running", "rs-2", "2", "Running"),
+        ];
+        let names = |owner: &Owner| -> Vec<String> {
+            candidates(&pods, owner)
+                .iter()
+                .flat_map(|pod| pod.metadata.name.clone())
+                .collect()
+        };
+
+        let deployment = Owner {
+            controllers: vec!["rs-1".to_string(), "rs-2".to_string()],
+            current: Some(Revision::Controller("rs-2".to_string())),
+        };
+        assert_eq!(names(&deployment), vec!["new-running", "new-pending"]);
+
+        let no_current_pods = Owner {
+            current: Some(Revision::Controller("rs-3".to_string())),
+            ..deployment
+        };
+        assert_eq!(
+            names(&no_current_pods),
+            vec!["old-running", "new-running", "new-pending"]
+        );
+
+        let statefulset = Owner {
+            controllers: vec!["rs-1".to_string(), "rs-2".to_string()],
+            current: Some(Revision::Hash("1".to_string())),
+        };
+        assert_eq!(names(&statefulset), vec!["old-running"]);
+    }
+
+    fn objects() -> Objects {
+        Objects {
+            configmaps: BTreeMap::from([
+                (
+                    "app".to_string(),
+                    Lookup::Found(BTreeMap::from([
+                        ("LOG_LEVEL".to_string(), b"debug".to_vec()),
+                        ("HOST".to_string(), b"billing".to_vec()),
+                        ("application.yaml".to_string(), b"a: 1".to_vec()),
+                        ("1ST".to_string(), b"x".to_vec()),
+                    ])),
+                ),
+                ("feature-flags".to_string(), Lookup::Missing),
+            ]),
+            secrets: BTreeMap::from([
+                (
+                    "db".to_string(),
+                    Lookup::Found(BTreeMap::from([(
+                        "PASSWORD".to_string(),
+                        b"hunter2".to_vec(),
+                    )])),
+                ),
+                ("api".to_string(), Lookup::Missing),
+            ]),
+        }
+    }
+
+    #[test]
+    fn sources_are_resolved_in_kubelet_order() {
+        let pod = pod();
+        let container = &pod.spec.as_ref().unwrap().containers[0];
+        let variables = resolve(&pod, container, &objects());
+        let find = |name: &str| {
+            variables
+                .iter()
+                .rev()
+                .find(|v| v.name == name)
+                .unwrap_or_else(|| panic!("{}", name))
+        };
+
+        let overridden: Vec<&str> = variables
+            .iter()
+            .filter(|v| v.overridden)
+            .map(|v| v.source.as_str())
+            .collect();
+        assert_eq!(overridden, vec!["envFrom configmap/app"]);
+        assert_eq!(find("LOG_LEVEL").value, Value::Text("info".to_string()));
+        assert_eq!(
+            find("application.yaml").value,
+            Value::Text("a: 1".to_string())
+        );
+        assert!(matches!(find("1ST").value, Value::Unset(_)));
+        assert_eq!(find("DB_PASSWORD").value, Value::Masked(7));
+        assert!(matches!(find("*").value, Value::Unset(_)));
+        assert_eq!(
+            find("URL").value,
+            Value::Text("http://billing:8080/$(UNKNOWN)/$(HOST)".to_string())
+        );
+        assert_eq!(
+            find("DSN").value,
+            Value::Masked("postgres://app:hunter2@db".len())
+        );
+        assert_eq!(
+            find("REGION").value,
+            Value::Error("missing key REGION in configmap/app".to_string())
+        );
+        assert_eq!(
+            find("TOKEN").value,
+            Value::Error("missing secret/api".to_string())
+        );
+        assert_eq!(find("TOKEN").source, "secret/api key token");
+    }
+
+    #[test]
+    fn downward_api_fields_and_resources() {
+        let pod = pod();
+        let container = &pod.spec.as_ref().unwrap().containers[0];
+        let variables = resolve(&pod, container, &objects());
+        let value = |name: &str| &variables.iter().find(|v| v.name == name).unwrap().value;
+
+        assert_eq!(value("POD_NAME"), &Value::Text("billing-7d9f".to_string()));
+        assert_eq!(value("APP"), &Value::Text("billing".to_string()));
+        assert!(matches!(value("POD_IP"), Value::Runtime(_)));
+        assert_eq!(value("CPU"), &Value::Text("500".to_string()));
+        assert_eq!(value("MEMORY"), &Value::Text("512".to_string()));
+        assert_eq!(
+            value("CPU_LIMIT"),
+            &Value::Runtime("<node allocatable>".to_string())
+        );
+    }
+}
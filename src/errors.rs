@@ -3,7 +3,7 @@ use gitlab::{
     GitlabError,
 };
 use kube::config::{InferConfigError, KubeconfigError};
-use std::{error, fmt, string::FromUtf8Error};
+use std::{error, fmt, io, string::FromUtf8Error};
 
 #[derive(Debug)]
 pub enum MacgyverCmdErrors {
@@ -18,6 +18,12 @@ pub enum MacgyverCmdErrors {
     HttpClient(reqwest::Error),
     Utf8Conversion(FromUtf8Error),
     YamlError(serde_yaml::Error),
+    Io(io::Error),
+    PolicyViolations(usize),
+    ExpiringCertificates(usize, i64),
+    AccessDenied(String),
+    DanglingReferences(usize),
+    InvalidConfigValues(usize),
     Processing(Box<dyn error::Error>),
 }
 
@@ -25,53 +31,67 @@ impl fmt::Display for MacgyverCmdErrors {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             MacgyverCmdErrors::CliParameterMissing(ref error) => {
-                write!(f, "Missing parameters/env vars: {}", error.to_string())
+                write!(f, "Missing parameters/env vars: {}", error)
             }
             MacgyverCmdErrors::KubeConfig(ref error) => {
-                write!(f, "Error KubeConfig: {}", error.to_string())
+                write!(f, "Error KubeConfig: {}", error)
             }
             MacgyverCmdErrors::K8sCanNotCreateClient(ref error) => {
-                write!(f, "Can not create k8s client: {}", error.to_string())
+                write!(f, "Can not create k8s client: {}", error)
             }
             MacgyverCmdErrors::K8sBuildRequest(ref error) => {
-                write!(f, "Error when building k8s request: {}", error.to_string())
-            }
-            MacgyverCmdErrors::GitlabScope(ref error) => write!(
-                f,
-                "Error when GitlabScope API response: {}",
-                error.to_string()
-            ),
-            MacgyverCmdErrors::GitlabCanNotCreateClient(ref error) => write!(
-                f,
-                "Error when creating gitlab client: {}",
-                error.to_string()
-            ),
-            MacgyverCmdErrors::GitlabBuildRequest(ref error) => write!(
-                f,
-                "Error when building gitlab request: {}",
-                error.to_string()
-            ),
-            MacgyverCmdErrors::GitlabPaged(ref error) => write!(
-                f,
-                "Error when using the page api from gitlab: {}",
-                error.to_string()
-            ),
+                write!(f, "Error when building k8s request: {}", error)
+            }
+            MacgyverCmdErrors::GitlabScope(ref error) => {
+                write!(f, "Error when GitlabScope API response: {}", error)
+            }
+            MacgyverCmdErrors::GitlabCanNotCreateClient(ref error) => {
+                write!(f, "Error when creating gitlab client: {}", error)
+            }
+            MacgyverCmdErrors::GitlabBuildRequest(ref error) => {
+                write!(f, "Error when building gitlab request: {}", error)
+            }
+            MacgyverCmdErrors::GitlabPaged(ref error) => {
+                write!(f, "Error when using the page api from gitlab: {}", error)
+            }
             MacgyverCmdErrors::YamlError(ref error) => {
-                write!(f, "Error when deserializing yaml: {}", error.to_string())
+                write!(f, "Error when deserializing yaml: {}", error)
+            }
+            MacgyverCmdErrors::Io(ref error) => {
+                write!(f, "Error while reading/writing file: {}", error)
+            }
+            MacgyverCmdErrors::PolicyViolations(count) => {
+                write!(f, "Found {} resource policy violations", count)
+            }
+            MacgyverCmdErrors::ExpiringCertificates(count, days) => {
+                write!(
+                    f,
+                    "Found {} certificates expiring within {} days or unreadable",
+                    count, days
+                )
+            }
+            MacgyverCmdErrors::AccessDenied(ref error) => {
+                write!(f, "Access denied: {}", error)
+            }
+            MacgyverCmdErrors::DanglingReferences(count) => {
+                write!(
+                    f,
+                    "Found {} references to missing configmaps, secrets or keys",
+                    count
+                )
+            }
+            MacgyverCmdErrors::InvalidConfigValues(count) => {
+                write!(f, "Found {} configmap values that do not parse", count)
             }
             MacgyverCmdErrors::Processing(ref error) => {
-                write!(f, "Error while processing: {}", error.to_string())
-            }
-            MacgyverCmdErrors::HttpClient(ref error) => write!(
-                f,
-                "Error while doing as client http request: {}",
-                error.to_string()
-            ),
-            MacgyverCmdErrors::Utf8Conversion(ref error) => write!(
-                f,
-                "Error while converting utf8 to string: {}",
-                error.to_string()
-            ),
+                write!(f, "Error while processing: {}", error)
+            }
+            MacgyverCmdErrors::HttpClient(ref error) => {
+                write!(f, "Error while doing as client http request: {}", error)
+            }
+            MacgyverCmdErrors::Utf8Conversion(ref error) => {
+                write!(f, "Error while converting utf8 to string: {}", error)
+            }
         }
     }
 }
@@ -126,6 +146,12 @@ impl From<serde_yaml::Error> for MacgyverCmdErrors {
     }
 }
 
+impl From<io::Error> for MacgyverCmdErrors {
+    fn from(error: io::Error) -> Self {
+        MacgyverCmdErrors::Io(error)
+    }
+}
+
 impl From<FromUtf8Error> for MacgyverCmdErrors {
     fn from(error: FromUtf8Error) -> Self {
         MacgyverCmdErrors::Utf8Conversion(error)
@@ -0,0 +1,275 @@
+use crate::cli_args::ExportFormat;
+use crate::errors::MacgyverCmdErrors;
+use crate::Result;
+use kube::Resource;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+
+/// An object to export and its values, decoded, for the dotenv format
+pub struct Exported<K> {
+    pub object: K,
+    pub values: BTreeMap<String, Vec<u8>>,
+}
+
+/// Drops what the API server sets and what ties the object to its cluster (owners, deletion),
+/// the manifest can be applied again to any cluster
+pub fn clean<K: Resource>(object: &mut K) {
+    let meta = object.meta_mut();
+    meta.owner_references = None;
+    meta.finalizers = None;
+    meta.deletion_timestamp = None;
+    meta.deletion_grace_period_seconds = None;
+    meta.managed_fields = None;
+    meta.resource_version = None;
+    meta.uid = None;
+    meta.annotations = None;
+    meta.creation_timestamp = None;
+    meta.self_link = None;
+    meta.generation = None;
+}
+
+/// One file per object in `out_dir`, or every object on stdout.
+/// Values are decoded, so the directory and the files are only readable by the user
+pub fn write<K: Resource + Serialize>(
+    format: ExportFormat,
+    out_dir: Option<&Path>,
+    exported: Vec<Exported<K>>,
+) -> Result<()> {
+    match out_dir {
+        Some(out_dir) => {
+            create_private_dir(out_dir)?;
+            for object in exported {
+                let name = object.object.meta().name.clone().unwrap_or_default();
+                let path = out_dir.join(format!("{}.{}", name, extension(format)));
+                let content = render(format, std::slice::from_ref(&object))?;
+                create_private_file(&path)?.write_all(content.as_bytes())?;
+                eprintln!("wrote {}", path.display());
+            }
+        }
+        None => print!("{}", render(format, &exported)?),
+    }
+    Ok(())
+}
+
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    builder.mode(0o700);
+    builder.create(dir)
+}
+
+/// 0600 also when the file already exists, `mode` only applies to new files
+fn create_private_file(path: &Path) -> io::Result<File> {
+    let mut options = File::options();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let file = options.open(path)?;
+    #[cfg(unix)]
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    Ok(file)
+}
+
+fn extension(format: ExportFormat) -> &'static str {
+    match format {
+        ExportFormat::Dotenv => "env",
+        ExportFormat::Json => "json",
+        ExportFormat::Yaml => "yaml",
+    }
+}
+
+fn render<K: Resource + Serialize>(
+    format: ExportFormat,
+    exported: &[Exported<K>],
+) -> Result<String> {
+    match format {
+        ExportFormat::Dotenv => Ok(exported
+            .iter()
+            .map(|object| {
+                dotenv(
+                    object.object.meta().name.as_deref().unwrap_or_default(),
+                    &object.values,
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")),
+        ExportFormat::Yaml => Ok(exported
+            .iter()
+            .map(|object| serde_yaml::to_string(&object.object))
+            .collect::<core::result::Result<Vec<String>, _>>()?
+            .join("---\n")),
+        ExportFormat::Json => {
+            // Several objects are wrapped in a v1 List, `kubectl apply -f` takes both
+            let value = match exported {
+                [object] => serde_json::to_value(&object.object),
+                objects => objects
+                    .iter()
+                    .map(|object| serde_json::to_value(&object.object))
+                    .collect::<core::result::Result<Vec<_>, _>>()
+                    .map(|items| json!({"apiVersion": "v1", "kind": "List", "items": items})),
+            };
+            value
+                .and_then(|value| serde_json::to_string_pretty(&value))
+                .map(|json| json + "\n")
+                .map_err(|error| MacgyverCmdErrors::Processing(error.into()))
+        }
+    }
+}
+
+/// `KEY="value"` lines, keys that are not variable names and binary values are left as comments
+fn dotenv(name: &str, values: &BTreeMap<String, Vec<u8>>) -> String {
+    let mut lines = vec![format!("# {}", name)];
+    for (key, value) in values {
+        if !is_variable_name(key) {
+            lines.push(format!("# {} skipped: not a variable name", key));
+            continue;
+        }
+        match std::str::from_utf8(value) {
+            Ok(value) => lines.push(format!("{}=\"{}\"", key, escape(value))),
+            Err(_) => lines.push(format!(
+                "# {} skipped: binary value, {} bytes",
+                key,
+                value.len()
+            )),
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+fn is_variable_name(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Escaped for double quotes, `$` and backticks too so that sourcing the file in a
+/// shell or loading it with dotenv/compose does not expand anything
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('$', "\\$")
+        .replace('`', "\\`")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::ConfigMap;
+
+    fn configmap(name: &str) -> Exported<ConfigMap> {
+        let mut object: ConfigMap = serde_json::from_value(json!({
+            "metadata": {
+                "name": name,
+                "namespace": "billing",
+                "uid": "6a1f",
+                "resourceVersion": "42",
+                "creationTimestamp": "2026-01-01T00:00:00Z",
+                "labels": {"app": "billing"},
+                "annotations": {"kubectl.kubernetes.io/last-applied-configuration": "{}"},
+                "managedFields": [{"manager": "kubectl", "operation": "Apply"}],
+                "ownerReferences": [{"apiVersion": "v1", "kind": "Service", "name": "app", "uid": "9b2c"}],
+                "finalizers": ["example.com/cleanup"],
+                "deletionTimestamp": "2026-01-02T00:00:00Z",
+                "deletionGracePeriodSeconds": 30
+            },
+            "data": {"LOG_LEVEL": "debug"}
+        }))
+        .unwrap();
+        clean(&mut object);
+        Exported {
+            object,
+            values: BTreeMap::from([("LOG_LEVEL".to_string(), b"debug".to_vec())]),
+        }
+    }
+
+    #[test]
+    fn manifests_are_stripped_of_server_fields() {
+        let yaml = render(ExportFormat::Yaml, &[configmap("app"), configmap("db")]).unwrap();
+        let objects: Vec<&str> = yaml.split("---\n").collect();
+        assert_eq!(objects.len(), 2);
+        let object: serde_yaml::Value = serde_yaml::from_str(objects[0]).unwrap();
+        assert_eq!(object["apiVersion"], "v1");
+        assert_eq!(object["kind"], "ConfigMap");
+        assert_eq!(object["metadata"]["labels"]["app"], "billing");
+        for field in [
+            "uid",
+            "resourceVersion",
+            "creationTimestamp",
+            "annotations",
+            "managedFields",
+            "ownerReferences",
+            "finalizers",
+            "deletionTimestamp",
+            "deletionGracePeriodSeconds",
+        ] {
+            assert!(object["metadata"].get(field).is_none(), "{}", field);
+        }
+
+        let json = render(ExportFormat::Json, &[configmap("app"), configmap("db")]).unwrap();
+        let list: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(list["kind"], "List");
+        assert_eq!(list["items"][1]["metadata"]["name"], "db");
+        let json = render(ExportFormat::Json, &[configmap("app")]).unwrap();
+        let object: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(object["kind"], "ConfigMap");
+    }
+
+    #[test]
+    fn dotenv_quotes_values_and_skips_what_it_can_not_hold() {
+        let values = BTreeMap::from([
+            ("DATABASE_URL".to_string(), b"postgres://db".to_vec()),
+            ("MOTD".to_string(), b"say \"hi\"\nC:\\".to_vec()),
+            ("PASSWORD".to_string(), b"pa$$word`id`\r".to_vec()),
+            ("application.yaml".to_string(), b"a: 1".to_vec()),
+            ("KEYSTORE".to_string(), vec![0xff, 0xfe]),
+        ]);
+
+        assert_eq!(
+            dotenv("app", &values),
+            "# app\n\
+             DATABASE_URL=\"postgres://db\"\n\
+             # KEYSTORE skipped: binary value, 2 bytes\n\
+             MOTD=\"say \\\"hi\\\"\\nC:\\\\\"\n\
+             PASSWORD=\"pa\\$\\$word\\`id\\`\\r\"\n\
+             # application.yaml skipped: not a variable name\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exported_files_are_only_readable_by_the_user() {
+        let dir = std::env::temp_dir()
+            .join(format!("macgyver-export-{}", std::process::id()))
+            .join("secrets");
+        fs::create_dir_all(&dir).unwrap();
+        let stale = dir.join("app.env");
+        fs::write(&stale, "old").unwrap();
+        fs::set_permissions(&stale, fs::Permissions::from_mode(0o644)).unwrap();
+
+        write(ExportFormat::Dotenv, Some(&dir), vec![configmap("app")]).unwrap();
+        let private = dir.parent().unwrap().join("private");
+        write(ExportFormat::Yaml, Some(&private), vec![configmap("db")]).unwrap();
+
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&stale), 0o600);
+        assert_eq!(
+            fs::read_to_string(&stale).unwrap(),
+            "# app\nLOG_LEVEL=\"debug\"\n"
+        );
+        assert_eq!(mode(&private), 0o700);
+        assert_eq!(mode(&private.join("db.yaml")), 0o600);
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,280 @@
+#[cfg(feature = "secret")]
+use crate::access::{can_list_secrets, confirm_context, confirm_named_context, AuditLog};
+use crate::cli_args::GrepArgs;
+use crate::errors::MacgyverCmdErrors;
+use crate::{get_k8s_env, k8s_client, Result};
+use colored::Colorize;
+#[cfg(feature = "secret")]
+use k8s_openapi::api::core::v1::Secret;
+use k8s_openapi::api::core::v1::{ConfigMap, Namespace};
+use kube::api::ListParams;
+use kube::{api::Api, Client};
+use regex::{Regex, RegexBuilder};
+use std::collections::BTreeMap;
+
+/// Longest excerpt of a value printed next to a match
+const EXCERPT_LENGTH: usize = 100;
+
+#[derive(Debug, PartialEq)]
+struct Hit {
+    /// `context/namespace/object/key`
+    path: String,
+    kind: &'static str,
+    /// Matching line of the value, never set for secrets
+    excerpt: Option<String>,
+}
+
+/// Which parts of an object are searched and shown
+#[derive(Clone, Copy)]
+struct Scope {
+    kind: &'static str,
+    search_values: bool,
+    show_values: bool,
+}
+
+const CONFIGMAP: Scope = Scope {
+    kind: "configmap",
+    search_values: true,
+    show_values: true,
+};
+
+#[tokio::main]
+pub async fn process(args: GrepArgs) -> Result<()> {
+    let regex = RegexBuilder::new(&args.pattern)
+        .case_insensitive(args.ignore_case)
+        .build()
+        .map_err(|error| {
+            MacgyverCmdErrors::CliParameterMissing(format!("Invalid regex: {}", error))
+        })?;
+    let contexts = match args.contexts.is_empty() {
+        true => vec![None],
+        false => args.contexts.iter().map(Some).collect(),
+    };
+
+    let mut hits = vec![];
+    for context in contexts {
+        let (label, client) = match context {
+            Some(context) => (context.to_uppercase(), k8s_client(Some(context)).await?),
+            None => (get_k8s_env()?, Client::try_default().await?),
+        };
+        // Matching values is a way to guess them: same confirmation and audit as a reveal
+        #[cfg(feature = "secret")]
+        let audit = match args.secret_values {
+            true => {
+                match context {
+                    Some(context) => confirm_named_context(context)?,
+                    None => confirm_context()?,
+                }
+                Some(AuditLog::open_for(context.map(String::as_str))?)
+            }
+            false => None,
+        };
+        let namespaces = match args.all_namespaces {
+            true => Api::<Namespace>::all(client.clone())
+                .list(&ListParams::default())
+                .await?
+                .into_iter()
+                .flat_map(|namespace| namespace.metadata.name)
+                .collect(),
+            false => args.namespaces.clone(),
+        };
+        for namespace in &namespaces {
+            let prefix = format!("{}/{}", label, namespace);
+            let configmaps = Api::<ConfigMap>::namespaced(client.clone(), namespace)
+                .list(&ListParams::default())
+                .await?;
+            for configmap in configmaps {
+                let name = configmap.metadata.name.unwrap_or_default();
+                let data = configmap
+                    .data
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(key, value)| (key, value.into_bytes()))
+                    .collect();
+                hits.extend(search(&regex, &prefix, &name, &data, CONFIGMAP));
+            }
+
+            #[cfg(feature = "secret")]
+            if args.secrets || args.secret_values {
+                hits.extend(
+                    search_secrets(&client, namespace, &regex, &prefix, audit.as_ref()).await?,
+                );
+            }
+        }
+    }
+
+    println!("{0: <80} {1: <10} match", "path", "kind");
+    hits.iter().for_each(|hit| print_hit(hit, &regex));
+    Ok(())
+}
+
+/// Behind the same access check as `secret`: keys only, values are searched with
+/// `--secret-values` (given its audit log) but never printed
+#[cfg(feature = "secret")]
+async fn search_secrets(
+    client: &Client,
+    namespace: &str,
+    regex: &Regex,
+    prefix: &str,
+    audit: Option<&AuditLog>,
+) -> Result<Vec<Hit>> {
+    if !can_list_secrets(client.clone(), namespace).await? {
+        return Ok(vec![]);
+    }
+    let scope = Scope {
+        kind: "secret",
+        search_values: audit.is_some(),
+        show_values: false,
+    };
+    let secrets = Api::<Secret>::namespaced(client.clone(), namespace)
+        .list(&ListParams::default())
+        .await?;
+    let mut hits = vec![];
+    for secret in secrets {
+        let name = secret.metadata.name.unwrap_or_default();
+        let data: BTreeMap<String, Vec<u8>> = secret
+            .data
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(key, value)| (key, value.0))
+            .collect();
+        if let Some(audit) = audit {
+            let keys: Vec<&str> = data.keys().map(String::as_str).collect();
+            audit.record_search(namespace, &name, &keys)?;
+        }
+        hits.extend(search(regex, prefix, &name, &data, scope));
+    }
+    Ok(hits)
+}
+
+/// One hit per matching key, and per matching line of a value
+fn search(
+    regex: &Regex,
+    prefix: &str,
+    name: &str,
+    data: &BTreeMap<String, Vec<u8>>,
+    scope: Scope,
+) -> Vec<Hit> {
+    let mut hits = vec![];
+    for (key, value) in data {
+        let path = format!("{}/{}/{}", prefix, name, key);
+        let text = std::str::from_utf8(value).ok();
+        let lines: Vec<&str> = match (scope.search_values, text) {
+            (true, Some(text)) => text.lines().filter(|line| regex.is_match(line)).collect(),
+            _ => vec![],
+        };
+
+        if regex.is_match(key) && (lines.is_empty() || !scope.show_values) {
+            let excerpt = text
+                .filter(|_| scope.show_values)
+                .map(|text| excerpt(text.lines().next().unwrap_or_default()));
+            hits.push(Hit {
+                path,
+                kind: scope.kind,
+                excerpt,
+            });
+        } else if !scope.show_values && !lines.is_empty() {
+            hits.push(Hit {
+                path,
+                kind: scope.kind,
+                excerpt: None,
+            });
+        } else {
+            hits.extend(lines.into_iter().map(|line| Hit {
+                path: path.clone(),
+                kind: scope.kind,
+                excerpt: Some(excerpt(line)),
+            }));
+        }
+    }
+    hits
+}
+
+fn excerpt(line: &str) -> String {
+    let line = line.trim();
+    match line.char_indices().nth(EXCERPT_LENGTH) {
+        Some((index, _)) => format!("{}...", &line[..index]),
+        None => line.to_string(),
+    }
+}
+
+fn print_hit(hit: &Hit, regex: &Regex) {
+    let excerpt = match &hit.excerpt {
+        Some(excerpt) => regex
+            .replace_all(excerpt, |captures: &regex::Captures| {
+                captures[0].red().bold().to_string()
+            })
+            .to_string(),
+        None => "<value not shown>".dimmed().to_string(),
+    };
+    println!("{0: <80} {1: <10} {2}", hit.path, hit.kind, excerpt);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data() -> BTreeMap<String, Vec<u8>> {
+        BTreeMap::from([
+            ("FEATURE_X".to_string(), b"true".to_vec()),
+            (
+                "application.yaml".to_string(),
+                b"server:\n  port: 8080\nfeature_x: enabled\nfeature_y: off\n".to_vec(),
+            ),
+            ("LOG_LEVEL".to_string(), b"info".to_vec()),
+        ])
+    }
+
+    fn paths(hits: &[Hit]) -> Vec<(&str, Option<&str>)> {
+        hits.iter()
+            .map(|hit| (hit.path.as_str(), hit.excerpt.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn configmap_keys_and_value_lines() {
+        let regex = RegexBuilder::new("feature_x")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+
+        let hits = search(&regex, "PROD/billing", "app", &data(), CONFIGMAP);
+
+        assert_eq!(
+            paths(&hits),
+            vec![
+                ("PROD/billing/app/FEATURE_X", Some("true")),
+                (
+                    "PROD/billing/app/application.yaml",
+                    Some("feature_x: enabled")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn secret_values_are_never_shown() {
+        let regex = Regex::new("(?i)feature_x|8080").unwrap();
+        let keys_only = Scope {
+            kind: "secret",
+            search_values: false,
+            show_values: false,
+        };
+        let with_values = Scope {
+            search_values: true,
+            ..keys_only
+        };
+
+        let hits = search(&regex, "PROD/billing", "app", &data(), keys_only);
+        assert_eq!(paths(&hits), vec![("PROD/billing/app/FEATURE_X", None)]);
+
+        let hits = search(&regex, "PROD/billing", "app", &data(), with_values);
+        assert_eq!(
+            paths(&hits),
+            vec![
+                ("PROD/billing/app/FEATURE_X", None),
+                ("PROD/billing/app/application.yaml", None),
+            ]
+        );
+    }
+}
@@ -1,8 +1,13 @@
 use errors::MacgyverCmdErrors;
-use kube::config::Kubeconfig;
-use std::env;
+use kube::config::{KubeConfigOptions, Kubeconfig};
+use kube::Client;
+pub mod certificate;
 pub mod cli_args;
+pub mod diff;
 pub mod errors;
+pub mod export;
+pub mod metrics;
+pub mod quantity;
 
 pub mod pods;
 
@@ -12,13 +17,33 @@ pub mod configmap;
 #[cfg(feature = "cpumem")]
 pub mod cpumem;
 
+#[cfg(any(
+    feature = "secret",
+    feature = "certs",
+    feature = "env",
+    feature = "orphans"
+))]
+pub mod access;
 #[cfg(feature = "secret")]
 pub mod secret;
 
 #[cfg(feature = "logs")]
 pub mod logs;
 
-pub const PASSCHECK: &str = "This-is-my-pass-phrase";
+#[cfg(feature = "nodes")]
+pub mod nodes;
+
+#[cfg(feature = "certs")]
+pub mod certs;
+
+#[cfg(feature = "env")]
+pub mod env;
+
+#[cfg(feature = "orphans")]
+pub mod orphans;
+
+#[cfg(feature = "grep")]
+pub mod grep;
 
 pub type Result<T> = core::result::Result<T, MacgyverCmdErrors>;
 
@@ -32,24 +57,24 @@ pub fn get_k8s_env() -> Result<String> {
         .map_err(|err| err.into())
 }
 
-pub fn check_secret() -> Result<()> {
-    match env::var("MACGYVER_CMD_SECRET_CHECK") {
-        Ok(val) if val == PASSCHECK => Ok(()),
-        _ => {
-            return Err(MacgyverCmdErrors::CliParameterMissing(
-                "Missing/Invalid env var MACGYVER_CMD_SECRET_CHECK".to_string(),
-            ))
-        }
-    }
+/// Client for a kubeconfig context, the default client (in-cluster config included) when `None`
+pub async fn k8s_client(context: Option<&str>) -> Result<Client> {
+    let Some(context) = context else {
+        return Ok(Client::try_default().await?);
+    };
+    let options = KubeConfigOptions {
+        context: Some(context.to_string()),
+        ..KubeConfigOptions::default()
+    };
+    let config = kube::Config::from_kubeconfig(&options).await?;
+    Ok(Client::try_from(config)?)
 }
 
 pub fn check_gitlab_token() -> Result<String> {
-    match env::var("MACGYVER_CMD_GITLAB_TOKEN") {
+    match std::env::var("MACGYVER_CMD_GITLAB_TOKEN") {
         Ok(token) => Ok(token),
-        _ => {
-            return Err(MacgyverCmdErrors::CliParameterMissing(
-                "Missing env var MACGYVER_CMD_GITLAB_TOKEN".to_string(),
-            ))
-        }
+        _ => Err(MacgyverCmdErrors::CliParameterMissing(
+            "Missing env var MACGYVER_CMD_GITLAB_TOKEN".to_string(),
+        )),
     }
 }
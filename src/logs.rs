@@ -1,114 +1,676 @@
+mod context;
+mod framer;
+mod stats;
+
 use crate::cli_args::NamespaceAndService;
-use crate::cli_args::Services;
+use crate::cli_args::{SelectorOverride, Services};
+use crate::errors::MacgyverCmdErrors;
 use crate::{get_k8s_env, Result};
 use bytes::Bytes;
+use chrono::{DateTime, SecondsFormat, Utc};
 use colored::Colorize;
-use futures::stream::StreamExt;
-use k8s_openapi::api::core::v1::{Container, Pod};
+use context::LineSelector;
+use flate2::{write::GzEncoder, Compression};
+use framer::LineFramer;
+use futures::stream::{Stream, StreamExt};
+use k8s_openapi::api::core::v1::{Container, Pod, Service};
 use kube::api::{ListParams, LogParams};
+use kube::core::{request, Request};
 use kube::{Api, Client};
 use serde::{Deserialize, Serialize};
-use std::io::Read;
+use stats::{LevelMatcher, LogStats};
+use std::collections::{hash_map::Entry, HashMap};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tokio::sync::mpsc::{self, Sender}; // for `next`
+use tokio::time;
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
-struct K8sLogOutputRaw {
+struct K8sLogLine {
+    service: String,
+    pod: String,
+    container: String,
+    line: String,
+}
+
+/// Where the followed log lines end up
+enum Output {
+    Stdout,
+    Files(PathBuf, HashMap<PathBuf, File>),
+    Stats(LogStats),
+}
+
+impl Output {
+    fn write(&mut self, context: &str, log: &K8sLogLine, line: &str) -> Result<()> {
+        match self {
+            Output::Stdout => println!("{}-{} - {}", context.green(), log.pod.yellow(), line),
+            Output::Files(dir, files) => {
+                let path = dir.join(format!("{}_{}.log", log.pod, log.container));
+                let file = match files.entry(path) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        let file = File::options()
+                            .create(true)
+                            .append(true)
+                            .open(entry.key())?;
+                        entry.insert(file)
+                    }
+                };
+                writeln!(file, "{}", line)?;
+            }
+            Output::Stats(stats) => stats.record(&log.service, line),
+        }
+        Ok(())
+    }
+
+    fn refresh(&mut self, context: &str) {
+        if let Output::Stats(stats) = self {
+            stats.render(context)
+        }
+    }
+}
+
+/// What the follow tasks report back to the printing loop
+enum LogEvent {
+    Data(K8sLogLine),
+    Retrying {
+        pod: String,
+        attempt: u32,
+        delay: Duration,
+        error: String,
+    },
+    Failed {
+        pod: String,
+        error: String,
+    },
+    Ended {
+        pod: String,
+    },
+}
+
+struct LogSource {
+    pod: String,
+    container: String,
+    image: String,
+    params: LogParams,
+}
+
+#[derive(Debug, Serialize)]
+struct BundleManifest {
+    context: String,
+    collected_at: DateTime<Utc>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    entries: Vec<BundleEntry>,
+}
+
+#[derive(Debug, Serialize)]
+struct BundleEntry {
+    namespace: String,
     service: String,
-    raw: Vec<u8>,
+    pod: String,
+    container: String,
+    image: String,
+    file: String,
+    lines: usize,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+}
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Selector template meaning "use the selector of the k8s Service with the same name"
+const SERVICE_SELECTOR: &str = "@service";
+
+/// Maps a `namespace:service` to the label selector of its pods
+struct Selectors {
+    default: String,
+    overrides: Vec<SelectorOverride>,
+}
+
+impl Selectors {
+    fn template(&self, namespace: &str, service: &str) -> &str {
+        let scope = format!("{}:{}", namespace, service);
+        self.overrides
+            .iter()
+            .find(|o| o.scope == scope)
+            .or_else(|| self.overrides.iter().find(|o| o.scope == namespace))
+            .map(|o| o.template.as_str())
+            .unwrap_or(self.default.as_str())
+    }
+
+    async fn resolve(&self, client: &Client, namespace: &str, service: &str) -> Result<String> {
+        let template = self.template(namespace, service);
+        if template != SERVICE_SELECTOR {
+            return Ok(template
+                .replace("{namespace}", namespace)
+                .replace("{service}", service));
+        }
+
+        let api: Api<Service> = Api::namespaced(client.clone(), namespace);
+        let selector = api
+            .get(service)
+            .await?
+            .spec
+            .and_then(|spec| spec.selector)
+            .filter(|selector| !selector.is_empty())
+            .ok_or_else(|| {
+                MacgyverCmdErrors::Processing(
+                    format!("Service {}:{} has no selector", namespace, service).into(),
+                )
+            })?;
+        Ok(selector
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<String>>()
+            .join(","))
+    }
 }
 
-async fn get_pods_per_service(api: &Api<Pod>, service_name: String) -> Result<Vec<Pod>> {
-    let label = format!("app.kubernetes.io/instance={}", service_name);
+async fn get_pods_per_service(api: &Api<Pod>, selector: &str) -> Result<Vec<Pod>> {
     let pods = api
-        .list(&ListParams::default().labels(label.as_str()))
+        .list(&ListParams::default().labels(selector))
         .await?
         .into_iter()
         .collect::<Vec<Pod>>();
     Ok(pods)
 }
 
-fn build_log_params(pod: Pod, service: &str) -> Option<(String, LogParams)> {
-    let params_default = LogParams::default();
-    let name = pod.metadata.name?;
-    pod.spec?
-        .containers
-        .iter()
-        .filter(|container| container.name.contains(service))
-        .collect::<Vec<&Container>>()
-        .first()
-        .map(|container| {
-            (
-                name,
-                LogParams {
-                    container: Some(container.name.to_string()),
-                    follow: true,
-                    ..params_default
-                },
-            )
+/// Every container of the pod when writing files, else the container named after the service
+fn build_log_params(pod: Pod, service: &str, follow: bool, all_containers: bool) -> Vec<LogSource> {
+    let (Some(name), Some(spec)) = (pod.metadata.name, pod.spec) else {
+        return vec![];
+    };
+    let containers: Vec<&Container> = match all_containers {
+        true => spec.containers.iter().collect(),
+        // Selectors from templates or Services do not guarantee that the container is named after the service
+        false => spec
+            .containers
+            .iter()
+            .find(|container| container.name.contains(service))
+            .or(spec.containers.first())
+            .into_iter()
+            .collect(),
+    };
+    containers
+        .into_iter()
+        .map(|container| LogSource {
+            pod: name.to_string(),
+            container: container.name.to_string(),
+            image: container.image.clone().unwrap_or("N/A".to_string()),
+            params: LogParams {
+                container: Some(container.name.to_string()),
+                follow,
+                // Followed streams resume from the last timestamp, see `follow_source`
+                timestamps: true,
+                ..LogParams::default()
+            },
         })
+        .collect()
+}
+
+/// Returns `false` once nobody listens to the channel anymore
+async fn send_message_to_channel(
+    service: String,
+    pod: String,
+    container: String,
+    tx: &Sender<LogEvent>,
+    line: String,
+) -> bool {
+    let log = K8sLogLine {
+        service,
+        pod,
+        container,
+        line,
+    };
+    tx.send(LogEvent::Data(log)).await.is_ok()
+}
+
+/// Forbidden or deleted pods will not come back, everything else (container not
+/// started yet, connection reset, ...) is worth another try
+fn is_retryable(error: &kube::Error) -> bool {
+    !matches!(error, kube::Error::Api(response) if response.code == 403 || response.code == 404)
+}
+
+fn backoff(attempt: u32) -> Duration {
+    INITIAL_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(MAX_BACKOFF)
+}
+
+/// `Api::log_stream` with a `sinceTime`, which `LogParams` does not have
+async fn log_stream(
+    api: &Api<Pod>,
+    pod: &str,
+    params: &LogParams,
+    since: Option<DateTime<Utc>>,
+) -> kube::Result<impl Stream<Item = kube::Result<Bytes>>> {
+    let mut req = Request::new(api.resource_url())
+        .logs(pod, params)
+        .map_err(kube::Error::BuildRequest)?;
+    if let Some(since) = since {
+        let uri = format!(
+            "{}&sinceTime={}",
+            req.uri(),
+            since.to_rfc3339_opts(SecondsFormat::Secs, true)
+        );
+        *req.uri_mut() = uri.parse::<http::Uri>().map_err(|error| {
+            kube::Error::BuildRequest(request::Error::BuildRequest(error.into()))
+        })?;
+    }
+    req.extensions_mut().insert("log_stream");
+    api.clone().into_client().request_text_stream(req).await
 }
 
-async fn send_message_to_channel(service: String, tx: &Sender<K8sLogOutputRaw>, message: Bytes) {
-    let data: std::result::Result<Vec<_>, _> = message.bytes().collect();
-    if let std::result::Result::Ok(bytes) = data {
-        let log = K8sLogOutputRaw {
-            service,
-            raw: bytes,
+/// A stream also ends when the API server or the kubelet closes it, that is only
+/// the end of the logs once the pod stopped
+async fn is_running(api: &Api<Pod>, pod: &str) -> bool {
+    match api.get_opt(pod).await {
+        Ok(Some(pod)) => pod
+            .status
+            .and_then(|status| status.phase)
+            .is_some_and(|phase| phase == "Running"),
+        Ok(None) => false,
+        Err(_) => true,
+    }
+}
+
+/// Lines are fetched with `timestamps`. On reconnect the stream starts again at
+/// `sinceTime`, which the API server truncates to the second, so the lines at or
+/// before the last one received are dropped instead of printed twice
+async fn follow_source(
+    api: Api<Pod>,
+    service: String,
+    source: LogSource,
+    retries: u32,
+    tx: Sender<LogEvent>,
+) {
+    let mut framer = LineFramer::default();
+    let mut last_seen: Option<DateTime<Utc>> = None;
+    let mut attempt = 0;
+
+    loop {
+        let error = match log_stream(&api, &source.pod, &source.params, last_seen).await {
+            Ok(log_stream) => {
+                let mut log_stream = Box::pin(log_stream);
+                let mut lines = vec![];
+                let error = loop {
+                    match log_stream.next().await {
+                        Some(Ok(data)) => {
+                            attempt = 0;
+                            lines.extend(framer.push(&data));
+                        }
+                        Some(Err(error)) => break Some(error.to_string()),
+                        None => break None,
+                    }
+                    for line in lines.drain(..) {
+                        if !send_line(&service, &source, &tx, &mut last_seen, &line).await {
+                            return;
+                        }
+                    }
+                };
+                if let Some(line) = framer.finish() {
+                    if !send_line(&service, &source, &tx, &mut last_seen, &line).await {
+                        return;
+                    }
+                }
+                match error {
+                    Some(error) => error,
+                    None if !is_running(&api, &source.pod).await => {
+                        tx.send(LogEvent::Ended { pod: source.pod }).await.ok();
+                        return;
+                    }
+                    None => "log stream ended".to_string(),
+                }
+            }
+            Err(error) if !is_retryable(&error) => {
+                let failed = LogEvent::Failed {
+                    pod: source.pod,
+                    error: error.to_string(),
+                };
+                tx.send(failed).await.ok();
+                return;
+            }
+            Err(error) => error.to_string(),
         };
-        tx.send(log).await.ok();
+
+        attempt += 1;
+        if attempt > retries {
+            let failed = LogEvent::Failed {
+                pod: source.pod,
+                error,
+            };
+            tx.send(failed).await.ok();
+            return;
+        }
+
+        let delay = backoff(attempt);
+        let retrying = LogEvent::Retrying {
+            pod: source.pod.to_string(),
+            attempt,
+            delay,
+            error,
+        };
+        if tx.send(retrying).await.is_err() {
+            return;
+        }
+        time::sleep(delay).await;
     }
 }
 
+/// Strips the timestamp of the line and skips what was already sent.
+/// Returns `false` once nobody listens to the channel anymore
+async fn send_line(
+    service: &str,
+    source: &LogSource,
+    tx: &Sender<LogEvent>,
+    last_seen: &mut Option<DateTime<Utc>>,
+    line: &str,
+) -> bool {
+    let (timestamp, line) = split_timestamp(line);
+    if let Some(timestamp) = timestamp {
+        if last_seen.is_some_and(|last_seen| timestamp <= last_seen) {
+            return true;
+        }
+        *last_seen = Some(timestamp);
+    }
+    send_message_to_channel(
+        service.to_string(),
+        source.pod.to_string(),
+        source.container.to_string(),
+        tx,
+        line.to_string(),
+    )
+    .await
+}
+
 #[tokio::main]
-pub async fn process(Services { services, pattern }: Services) -> Result<()> {
+pub async fn process(
+    Services {
+        services,
+        pattern,
+        after_context,
+        before_context,
+        context: context_lines,
+        selector,
+        selector_override,
+        out_dir,
+        bundle,
+        stats,
+        error_regex,
+        warn_regex,
+        level_field,
+        top,
+        retries,
+    }: Services,
+) -> Result<()> {
     let context = get_k8s_env()?;
     let client = Client::try_default().await?;
+
+    let selectors = Selectors {
+        default: selector,
+        overrides: selector_override,
+    };
+
+    if let Some(bundle) = bundle {
+        return write_bundle(client, context, services, &selectors, &bundle).await;
+    }
+
     let (tx, mut rx) = mpsc::channel(200);
+    let mut sources = 0;
+    let mut failed = 0;
 
     for NamespaceAndService { namespace, service } in services.into_iter() {
         let api: Api<Pod> = Api::namespaced(client.clone(), namespace.as_str());
-        let service_name = format!("{}-{}", namespace, service);
-        let pods = get_pods_per_service(&api, service_name)
+        let selector = selectors.resolve(&client, &namespace, &service).await?;
+        let label = format!("{}:{}", namespace, service);
+        let pods = get_pods_per_service(&api, &selector)
             .await?
             .into_iter()
-            .flat_map(|pod| build_log_params(pod, service.as_str()));
-
-        pods.into_iter().for_each(|(service, log_params)| {
-            let local_api: Api<Pod> = api.clone();
-            let local_tx = tx.clone();
-            tokio::spawn(async move {
-                let mut log_stream = local_api
-                    .log_stream(service.as_str(), &log_params)
-                    .await
-                    .expect("Trying to read log stream");
-                while let Some(Ok(data)) = log_stream.next().await {
-                    send_message_to_channel(service.to_string(), &local_tx, data).await;
+            .flat_map(|pod| build_log_params(pod, service.as_str(), true, out_dir.is_some()));
+
+        for source in pods {
+            sources += 1;
+            tokio::spawn(follow_source(
+                api.clone(),
+                label.to_string(),
+                source,
+                retries,
+                tx.clone(),
+            ));
+        }
+    }
+    // Only the follow tasks keep the channel open, so `recv` ends once they are all done
+    drop(tx);
+
+    if sources == 0 {
+        return Err(MacgyverCmdErrors::Processing(
+            "No pod found for the given services".into(),
+        ));
+    }
+
+    let mut output = match out_dir {
+        Some(dir) => {
+            fs::create_dir_all(&dir)?;
+            println!("Writing logs to {}", dir.display());
+            Output::Files(dir, HashMap::new())
+        }
+        None if stats => Output::Stats(LogStats::new(
+            LevelMatcher {
+                error: error_regex,
+                warn: warn_regex,
+                field: level_field,
+            },
+            top,
+        )),
+        None => Output::Stdout,
+    };
+    let mut selector = LineSelector::new(
+        pattern,
+        before_context.or(context_lines).unwrap_or(0),
+        after_context.or(context_lines).unwrap_or(0),
+    );
+    let mut refresh = time::interval(Duration::from_secs(1));
+
+    loop {
+        tokio::select! {
+            event = rx.recv() => {
+                let log = match event {
+                    Some(LogEvent::Data(log)) => log,
+                    Some(LogEvent::Retrying { pod, attempt, delay, error }) => {
+                        eprintln!(
+                            "{} {} - {} (attempt {}/{}, next in {}s)",
+                            "RETRYING".yellow(),
+                            pod.yellow(),
+                            error,
+                            attempt,
+                            retries,
+                            delay.as_secs()
+                        );
+                        continue;
+                    }
+                    Some(LogEvent::Failed { pod, error }) => {
+                        failed += 1;
+                        eprintln!("{} {} - {}", "FAILED".red(), pod.yellow(), error);
+                        continue;
+                    }
+                    Some(LogEvent::Ended { pod }) => {
+                        eprintln!("{} {} - pod is no longer running", "ENDED".blue(), pod.yellow());
+                        continue;
+                    }
+                    None => break,
+                };
+                let stream = format!("{}/{}", log.pod, log.container);
+                for line in selector.select(&stream, &log.line) {
+                    output.write(&context, &log, &line)?;
                 }
-            });
-        });
-    }
-
-    while let Some(log) = rx.recv().await {
-        let result_log_string = String::from_utf8(log.raw);
-        if let Ok(mut log_string) = result_log_string {
-            log_string.pop(); //Remove new line char
-            match pattern {
-                Some(ref p) if log_string.contains(p) => println!(
-                    "{}-{} - {}",
-                    context.green(),
-                    log.service.yellow(),
-                    log_string
-                ),
-                Some(_) => (),
-                None => println!(
-                    "{}-{} - {}",
-                    context.green(),
-                    log.service.yellow(),
-                    log_string
-                ),
             }
+            _ = refresh.tick() => output.refresh(&context),
         }
     }
 
+    if failed == sources {
+        return Err(MacgyverCmdErrors::Processing(
+            format!("All {} log streams failed", sources).into(),
+        ));
+    }
     Ok(())
 }
+
+async fn write_bundle(
+    client: Client,
+    context: String,
+    services: Vec<NamespaceAndService>,
+    selectors: &Selectors,
+    path: &Path,
+) -> Result<()> {
+    let collected_at = Utc::now();
+    let encoder = GzEncoder::new(File::create(path)?, Compression::default());
+    let mut archive = tar::Builder::new(encoder);
+    let mut entries: Vec<BundleEntry> = vec![];
+    let mut failed = 0;
+
+    for NamespaceAndService { namespace, service } in services.into_iter() {
+        let api: Api<Pod> = Api::namespaced(client.clone(), namespace.as_str());
+        let selector = selectors.resolve(&client, &namespace, &service).await?;
+        let sources = get_pods_per_service(&api, &selector)
+            .await?
+            .into_iter()
+            .flat_map(|pod| build_log_params(pod, service.as_str(), false, true));
+
+        for source in sources {
+            let logs = match api.logs(source.pod.as_str(), &source.params).await {
+                Ok(logs) => logs,
+                Err(error) => {
+                    failed += 1;
+                    let stream = format!("{}/{}", source.pod, source.container);
+                    eprintln!("{} {} - {}", "FAILED".red(), stream.yellow(), error);
+                    continue;
+                }
+            };
+            let file = format!(
+                "{}/{}/{}_{}.log",
+                namespace, service, source.pod, source.container
+            );
+            append_to_archive(&mut archive, &file, logs.as_bytes(), collected_at)?;
+            println!("{} {}", "Collected".green(), file.yellow());
+
+            let (from, to) = time_range(&logs);
+            entries.push(BundleEntry {
+                namespace: namespace.to_string(),
+                service: service.to_string(),
+                pod: source.pod,
+                container: source.container,
+                image: source.image,
+                file,
+                lines: logs.lines().count(),
+                from,
+                to,
+            });
+        }
+    }
+
+    if entries.is_empty() {
+        return Err(MacgyverCmdErrors::Processing(
+            format!("No logs collected ({} containers failed)", failed).into(),
+        ));
+    }
+
+    let manifest = BundleManifest {
+        context,
+        collected_at,
+        from: entries.iter().flat_map(|e| e.from).min(),
+        to: entries.iter().flat_map(|e| e.to).max(),
+        entries,
+    };
+    let manifest = serde_yaml::to_string(&manifest)?;
+    append_to_archive(
+        &mut archive,
+        "manifest.yaml",
+        manifest.as_bytes(),
+        collected_at,
+    )?;
+    archive.into_inner()?.finish()?;
+
+    println!("Bundle written to {}", path.display());
+    Ok(())
+}
+
+fn append_to_archive<W: Write>(
+    archive: &mut tar::Builder<W>,
+    path: &str,
+    content: &[u8],
+    mtime: DateTime<Utc>,
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime.timestamp() as u64);
+    archive.append_data(&mut header, path, content)?;
+    Ok(())
+}
+
+/// Logs are fetched with `timestamps`, so each line starts with an RFC3339 timestamp
+fn time_range(logs: &str) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+    let timestamp = |line| split_timestamp(line).0;
+    (
+        logs.lines().find_map(timestamp),
+        logs.lines().rev().find_map(timestamp),
+    )
+}
+
+/// The RFC3339 timestamp the kubelet puts in front of a line, and the line without it
+fn split_timestamp(line: &str) -> (Option<DateTime<Utc>>, &str) {
+    match line.split_once(' ') {
+        Some((ts, rest)) => match DateTime::parse_from_rfc3339(ts) {
+            Ok(ts) => (Some(ts.with_timezone(&Utc)), rest),
+            Err(_) => (None, line),
+        },
+        None => (None, line),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_source_per_container_when_writing_files() {
+        let pod = || -> Pod {
+            serde_json::from_value(serde_json::json!({
+                "metadata": {"name": "billing-7d9f"},
+                "spec": {"containers": [
+                    {"name": "istio-proxy", "image": "istio/proxyv2"},
+                    {"name": "billing-app", "image": "billing:1.2"}
+                ]}
+            }))
+            .unwrap()
+        };
+        let containers = |sources: Vec<LogSource>| -> Vec<String> {
+            sources.into_iter().map(|source| source.container).collect()
+        };
+
+        assert_eq!(
+            containers(build_log_params(pod(), "billing", true, false)),
+            vec!["billing-app"]
+        );
+        assert_eq!(
+            containers(build_log_params(pod(), "billing", false, true)),
+            vec!["istio-proxy", "billing-app"]
+        );
+    }
+
+    #[test]
+    fn timestamps_are_split_from_lines() {
+        let (timestamp, line) = split_timestamp("2026-03-01T10:00:00.123456789Z GET /health 200");
+        assert_eq!(
+            timestamp.map(|ts| ts.to_rfc3339_opts(SecondsFormat::Millis, true)),
+            Some("2026-03-01T10:00:00.123Z".to_string())
+        );
+        assert_eq!(line, "GET /health 200");
+        assert_eq!(split_timestamp("no timestamp"), (None, "no timestamp"));
+    }
+}
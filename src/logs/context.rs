@@ -0,0 +1,128 @@
+use std::collections::{HashMap, VecDeque};
+
+/// Printed between two groups of context lines that are not contiguous, like grep
+const SEPARATOR: &str = "--";
+
+#[derive(Default)]
+struct StreamContext {
+    before: VecDeque<String>,
+    after_remaining: usize,
+    printed: bool,
+    skipped: bool,
+}
+
+/// Picks the lines matching the pattern and their grep-like `-A/-B/-C` context.
+/// Context is kept per stream so lines of different pods never get mixed
+pub struct LineSelector {
+    pattern: Option<String>,
+    before: usize,
+    after: usize,
+    streams: HashMap<String, StreamContext>,
+}
+
+impl LineSelector {
+    pub fn new(pattern: Option<String>, before: usize, after: usize) -> Self {
+        Self {
+            pattern,
+            before,
+            after,
+            streams: HashMap::new(),
+        }
+    }
+
+    pub fn select(&mut self, stream: &str, line: &str) -> Vec<String> {
+        let Some(ref pattern) = self.pattern else {
+            return vec![line.to_string()];
+        };
+        let with_context = self.before > 0 || self.after > 0;
+        let context = self.streams.entry(stream.to_string()).or_default();
+        let mut selected = vec![];
+
+        if line.contains(pattern.as_str()) {
+            if with_context && context.printed && context.skipped {
+                selected.push(SEPARATOR.to_string());
+            }
+            selected.extend(context.before.drain(..));
+            selected.push(line.to_string());
+            context.after_remaining = self.after;
+            context.printed = true;
+            context.skipped = false;
+        } else if context.after_remaining > 0 {
+            context.after_remaining -= 1;
+            selected.push(line.to_string());
+        } else {
+            context.before.push_back(line.to_string());
+            if context.before.len() > self.before {
+                context.before.pop_front();
+                context.skipped = true;
+            }
+        }
+        selected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LineSelector;
+
+    fn select(before: usize, after: usize, lines: &[&str]) -> Vec<String> {
+        let mut selector = LineSelector::new(Some("ERROR".to_string()), before, after);
+        lines
+            .iter()
+            .flat_map(|line| selector.select("pod/app", line))
+            .collect()
+    }
+
+    #[test]
+    fn without_pattern_every_line_is_selected() {
+        let mut selector = LineSelector::new(None, 2, 2);
+        assert_eq!(selector.select("pod/app", "hello"), vec!["hello"]);
+    }
+
+    #[test]
+    fn without_context_only_matches_and_no_separator() {
+        assert_eq!(
+            select(0, 0, &["ERROR 1", "a", "b", "ERROR 2"]),
+            vec!["ERROR 1", "ERROR 2"]
+        );
+    }
+
+    #[test]
+    fn overlapping_windows_are_merged() {
+        assert_eq!(
+            select(1, 1, &["a", "ERROR 1", "b", "ERROR 2", "c", "d"]),
+            vec!["a", "ERROR 1", "b", "ERROR 2", "c"]
+        );
+        // Adjacent windows: the line after the first one is the line before the second one
+        assert_eq!(
+            select(1, 1, &["ERROR 1", "a", "b", "ERROR 2"]),
+            vec!["ERROR 1", "a", "b", "ERROR 2"]
+        );
+    }
+
+    #[test]
+    fn separator_between_groups_that_are_not_contiguous() {
+        assert_eq!(
+            select(1, 1, &["ERROR 1", "a", "b", "c", "ERROR 2", "d"]),
+            vec!["ERROR 1", "a", "--", "c", "ERROR 2", "d"]
+        );
+    }
+
+    #[test]
+    fn context_at_stream_start_and_end() {
+        // Fewer lines than -B before the first match, and no separator before the first group
+        assert_eq!(select(3, 0, &["a", "ERROR"]), vec!["a", "ERROR"]);
+        // The stream ends before -A lines followed the match
+        assert_eq!(select(0, 3, &["x", "ERROR", "a"]), vec!["ERROR", "a"]);
+        // Skipped lines before the first match do not add a separator
+        assert_eq!(select(1, 0, &["a", "b", "c", "ERROR"]), vec!["c", "ERROR"]);
+    }
+
+    #[test]
+    fn streams_keep_their_own_context() {
+        let mut selector = LineSelector::new(Some("ERROR".to_string()), 1, 0);
+        selector.select("pod/a", "from a");
+        selector.select("pod/b", "from b");
+        assert_eq!(selector.select("pod/a", "ERROR"), vec!["from a", "ERROR"]);
+    }
+}
@@ -0,0 +1,112 @@
+/// Turns the chunks of a log stream into lines.
+/// A chunk can hold several lines or end in the middle of one, so the incomplete
+/// tail is kept until the next chunk (or the end of the stream) completes it
+#[derive(Debug, Default)]
+pub struct LineFramer {
+    partial: Vec<u8>,
+}
+
+impl LineFramer {
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        let mut lines = vec![];
+        let mut start = 0;
+        for (index, _) in chunk.iter().enumerate().filter(|(_, byte)| **byte == b'\n') {
+            self.partial.extend_from_slice(&chunk[start..index]);
+            lines.push(decode(&self.partial));
+            self.partial.clear();
+            start = index + 1;
+        }
+        self.partial.extend_from_slice(&chunk[start..]);
+        lines
+    }
+
+    /// Last line of a stream that did not end with a new line
+    pub fn finish(&mut self) -> Option<String> {
+        if self.partial.is_empty() {
+            None
+        } else {
+            let line = decode(&self.partial);
+            self.partial.clear();
+            Some(line)
+        }
+    }
+}
+
+/// Invalid UTF-8 sequences are replaced instead of dropping the whole line
+fn decode(line: &[u8]) -> String {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    String::from_utf8_lossy(line).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LineFramer;
+
+    fn frame(chunks: &[&[u8]]) -> Vec<String> {
+        let mut framer = LineFramer::default();
+        let mut lines: Vec<String> = chunks.iter().flat_map(|chunk| framer.push(chunk)).collect();
+        lines.extend(framer.finish());
+        lines
+    }
+
+    #[test]
+    fn one_line_per_chunk() {
+        assert_eq!(frame(&[b"first\n", b"second\n"]), vec!["first", "second"]);
+    }
+
+    #[test]
+    fn several_lines_in_one_chunk() {
+        assert_eq!(
+            frame(&[b"first\nsecond\nthird\n"]),
+            vec!["first", "second", "third"]
+        );
+    }
+
+    #[test]
+    fn line_split_across_chunks() {
+        assert_eq!(
+            frame(&[b"fir", b"st\nsec", b"o", b"nd\n"]),
+            vec!["first", "second"]
+        );
+    }
+
+    #[test]
+    fn partial_line_is_kept_until_completed() {
+        let mut framer = LineFramer::default();
+        assert!(framer.push(b"no new line yet").is_empty());
+        assert_eq!(framer.push(b", now\n"), vec!["no new line yet, now"]);
+        assert_eq!(framer.finish(), None);
+    }
+
+    #[test]
+    fn last_line_without_new_line_is_flushed() {
+        assert_eq!(frame(&[b"first\nlast"]), vec!["first", "last"]);
+    }
+
+    #[test]
+    fn crlf_is_stripped() {
+        assert_eq!(
+            frame(&[b"first\r\nsecond\r", b"\n"]),
+            vec!["first", "second"]
+        );
+    }
+
+    #[test]
+    fn empty_lines_are_kept() {
+        assert_eq!(
+            frame(&[b"first\n\n", b"\nlast\n"]),
+            vec!["first", "", "", "last"]
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_is_decoded_lossily() {
+        assert_eq!(frame(&[b"bad \xff byte\n"]), vec!["bad \u{FFFD} byte"]);
+    }
+
+    #[test]
+    fn utf8_char_split_across_chunks() {
+        let bytes = "caf\u{e9}\n".as_bytes();
+        assert_eq!(frame(&[&bytes[..4], &bytes[4..]]), vec!["caf\u{e9}"]);
+    }
+}
@@ -0,0 +1,240 @@
+use colored::Colorize;
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Instant;
+
+/// Stop tracking new distinct messages past this point to bound memory
+const MAX_DISTINCT_MESSAGES: usize = 10_000;
+
+pub struct LevelMatcher {
+    pub error: Regex,
+    pub warn: Regex,
+    pub field: Option<String>,
+}
+
+enum Level {
+    Error,
+    Warn,
+    Other,
+}
+
+impl LevelMatcher {
+    fn level(&self, line: &str) -> Level {
+        let from_field = self.field.as_ref().and_then(|field| {
+            serde_json::from_str::<serde_json::Value>(line)
+                .ok()?
+                .get(field)?
+                .as_str()
+                .map(|level| level.to_string())
+        });
+        let text = from_field.as_deref().unwrap_or(line);
+
+        if self.error.is_match(text) {
+            Level::Error
+        } else if self.warn.is_match(text) {
+            Level::Warn
+        } else {
+            Level::Other
+        }
+    }
+}
+
+#[derive(Default)]
+struct ServiceStats {
+    total: u64,
+    window: u64,
+    errors: u64,
+    warns: u64,
+    messages: HashMap<String, u64>,
+}
+
+pub struct LogStats {
+    matcher: LevelMatcher,
+    top: usize,
+    uuid: Regex,
+    hex: Regex,
+    number: Regex,
+    services: BTreeMap<String, ServiceStats>,
+    window_start: Instant,
+}
+
+impl LogStats {
+    pub fn new(matcher: LevelMatcher, top: usize) -> Self {
+        Self {
+            matcher,
+            top,
+            uuid: Regex::new(
+                r"[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}",
+            )
+            .expect("Valid uuid regex"),
+            hex: Regex::new(r"\b(0x[0-9a-fA-F]+|[0-9a-fA-F]{12,})\b").expect("Valid hex regex"),
+            number: Regex::new(r"\d+(\.\d+)?").expect("Valid number regex"),
+            services: BTreeMap::new(),
+            window_start: Instant::now(),
+        }
+    }
+
+    pub fn record(&mut self, service: &str, line: &str) {
+        let level = self.matcher.level(line);
+        let message = self.normalize(line);
+        let stats = self.services.entry(service.to_string()).or_default();
+
+        stats.total += 1;
+        stats.window += 1;
+        match level {
+            Level::Error => stats.errors += 1,
+            Level::Warn => stats.warns += 1,
+            Level::Other => (),
+        }
+        let known = stats.messages.contains_key(&message);
+        if known || stats.messages.len() < MAX_DISTINCT_MESSAGES {
+            *stats.messages.entry(message).or_default() += 1;
+        }
+    }
+
+    /// Redraw the table and start a new lines/s window
+    pub fn render(&mut self, context: &str) {
+        let elapsed = self.window_start.elapsed().as_secs_f64().max(f64::EPSILON);
+        self.window_start = Instant::now();
+
+        print!("\x1B[2J\x1B[H");
+        println!("ENVIRONMENT: {}", context.green());
+        println!(
+            "{0: <50} {1: >10} {2: >12} {3: >10} {4: >10}",
+            "SERVICE", "LINES/S", "TOTAL", "ERRORS", "WARNS"
+        );
+        for (service, stats) in self.services.iter_mut() {
+            let rate = format!("{:.1}", stats.window as f64 / elapsed);
+            let errors = stats.errors.to_string();
+            let warns = stats.warns.to_string();
+            println!(
+                "{0: <50} {1: >10} {2: >12} {3: >10} {4: >10}",
+                service.yellow(),
+                rate,
+                stats.total,
+                if stats.errors > 0 {
+                    errors.red()
+                } else {
+                    errors.white()
+                },
+                if stats.warns > 0 {
+                    warns.yellow()
+                } else {
+                    warns.white()
+                },
+            );
+            stats.window = 0;
+        }
+
+        for (service, stats) in self.services.iter() {
+            let mut messages: Vec<(&String, &u64)> = stats.messages.iter().collect();
+            messages.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+            println!();
+            println!("{}", service.bold().white());
+            messages
+                .into_iter()
+                .take(self.top)
+                .for_each(|(message, count)| {
+                    println!("      {0: >8} {1}", count, message);
+                });
+        }
+    }
+
+    /// Replace UUIDs, hex ids (trace ids, hashes, addresses) and numbers so that messages
+    /// only differing by ids are grouped
+    fn normalize(&self, line: &str) -> String {
+        let line = self.uuid.replace_all(line, "<uuid>");
+        let line = self.hex.replace_all(&line, |captures: &regex::Captures| {
+            let hex = &captures[0];
+            match hex.bytes().all(|byte| byte.is_ascii_digit()) {
+                // Long numbers are left to the number regex
+                true => hex.to_string(),
+                false => "<hex>".to_string(),
+            }
+        });
+        self.number.replace_all(&line, "<n>").into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(field: Option<&str>) -> LevelMatcher {
+        LevelMatcher {
+            error: Regex::new(r"(?i)\b(error|fatal|panic|critical)\b").unwrap(),
+            warn: Regex::new(r"(?i)\bwarn(ing)?\b").unwrap(),
+            field: field.map(str::to_string),
+        }
+    }
+
+    fn level(matcher: &LevelMatcher, line: &str) -> &'static str {
+        match matcher.level(line) {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Other => "other",
+        }
+    }
+
+    #[test]
+    fn ids_and_numbers_are_normalized() {
+        let stats = LogStats::new(matcher(None), 5);
+        assert_eq!(
+            stats.normalize("request 7c9e6679-7425-40de-944b-e07fc1f90ae7 took 12.5 ms"),
+            "request <uuid> took <n> ms"
+        );
+        assert_eq!(
+            stats.normalize("trace 4bf92f3577b34da6a3ce929d0e0e4736 at 0x7ffd5e2c failed 3 times"),
+            "trace <hex> at <hex> failed <n> times"
+        );
+        assert_eq!(stats.normalize("order 123456789012 paid"), "order <n> paid");
+        assert_eq!(stats.normalize("cache miss"), "cache miss");
+    }
+
+    #[test]
+    fn level_from_the_line_or_the_json_field() {
+        let text = matcher(None);
+        assert_eq!(level(&text, "ERROR connection refused"), "error");
+        assert_eq!(level(&text, "Warning: slow query"), "warn");
+        assert_eq!(level(&text, "errors=0 all good"), "other");
+
+        let json = matcher(Some("level"));
+        assert_eq!(
+            level(&json, r#"{"level": "warn", "msg": "retry after error"}"#),
+            "warn"
+        );
+        assert_eq!(
+            level(&json, r#"{"level": "info", "msg": "fatal"}"#),
+            "other"
+        );
+        // Lines that are not JSON or miss the field fall back to the regexes
+        assert_eq!(level(&json, "panic: nil pointer"), "error");
+        assert_eq!(level(&json, r#"{"msg": "warning"}"#), "warn");
+    }
+
+    #[test]
+    fn distinct_messages_are_capped() {
+        let mut stats = LogStats::new(matcher(None), 5);
+        // Letters only, numbers would be normalized into the same message
+        let word = |mut index: usize| {
+            let mut word = String::new();
+            loop {
+                word.push((b'a' + (index % 26) as u8) as char);
+                index /= 26;
+                if index == 0 {
+                    return word;
+                }
+            }
+        };
+        for index in 0..MAX_DISTINCT_MESSAGES + 10 {
+            stats.record("billing", &format!("user {} logged in", word(index)));
+        }
+        stats.record("billing", "user a logged in");
+
+        let billing = &stats.services["billing"];
+        assert_eq!(billing.total, MAX_DISTINCT_MESSAGES as u64 + 11);
+        assert_eq!(billing.messages.len(), MAX_DISTINCT_MESSAGES);
+        // Known messages are still counted past the cap
+        assert_eq!(billing.messages["user a logged in"], 2);
+    }
+}
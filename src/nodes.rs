@@ -0,0 +1,284 @@
+use crate::cli_args::NodesArgs;
+use crate::quantity::{parse, CpuMemory};
+use crate::{get_k8s_env, Result};
+use colored::Colorize;
+use k8s_openapi::api::core::v1::{Container, Node, Pod};
+use kube::api::ListParams;
+use kube::{api::Api, Client};
+use std::collections::HashMap;
+
+/// Pods of a node that count against its allocatable resources
+#[derive(Debug, Default, PartialEq)]
+struct Allocated {
+    requests: CpuMemory,
+    limits: CpuMemory,
+    pods: usize,
+    namespace_pods: Vec<String>,
+}
+
+#[tokio::main]
+pub async fn process(NodesArgs { namespace, units }: NodesArgs) -> Result<()> {
+    let context = get_k8s_env()?;
+    let client = Client::try_default().await?;
+
+    let nodes = Api::<Node>::all(client.clone())
+        .list(&ListParams::default())
+        .await?
+        .items;
+    // Same pods as `kubectl describe node`: finished pods no longer hold resources
+    let pods = Api::<Pod>::all(client)
+        .list(&ListParams::default().fields("status.phase!=Succeeded,status.phase!=Failed"))
+        .await?
+        .items;
+    let allocated = allocated_per_node(&pods, &namespace);
+
+    println!("ENVIRONMENT: {context}");
+    println!(
+        "{0: <50} {1: <12} {2: <22} {3: <22} {4: <12} {5: <22} {6: <22} {7: <12}",
+        "node",
+        "cpu",
+        "cpu.requests",
+        "cpu.limits",
+        "memory",
+        "memory.requests",
+        "memory.limits",
+        "pods"
+    );
+    let empty = Allocated::default();
+    for node in &nodes {
+        let name = node.metadata.name.as_deref().unwrap_or("N/A");
+        let allocated = allocated.get(name).unwrap_or(&empty);
+        let allocatable = node
+            .status
+            .as_ref()
+            .and_then(|s| s.allocatable.clone())
+            .unwrap_or_default();
+        let capacity = CpuMemory::from_quantities(&allocatable);
+        let max_pods = allocatable.get("pods").and_then(|pods| parse(&pods.0));
+
+        println!(
+            "{0: <50} {1: <12} {2: <22} {3: <22} {4: <12} {5: <22} {6: <22} {7: <12}",
+            name,
+            units.cpu(capacity.cpu),
+            with_percent(
+                units.cpu(allocated.requests.cpu),
+                allocated.requests.cpu,
+                capacity.cpu
+            ),
+            with_percent(
+                units.cpu(allocated.limits.cpu),
+                allocated.limits.cpu,
+                capacity.cpu
+            ),
+            units.memory(capacity.memory),
+            with_percent(
+                units.memory(allocated.requests.memory),
+                allocated.requests.memory,
+                capacity.memory
+            ),
+            with_percent(
+                units.memory(allocated.limits.memory),
+                allocated.limits.memory,
+                capacity.memory
+            ),
+            format!(
+                "{}/{}",
+                allocated.pods,
+                max_pods
+                    .map(|max| max.to_string())
+                    .unwrap_or("N/A".to_string())
+            )
+        );
+    }
+
+    for node in &nodes {
+        let name = node.metadata.name.as_deref().unwrap_or("N/A");
+        println!();
+        println!("{} {}", "NODE".bold().white(), name);
+        print_taints(node);
+        print_conditions(node);
+        let namespace_pods = allocated
+            .get(name)
+            .map(|allocated| allocated.namespace_pods.join(", "))
+            .unwrap_or_default();
+        println!("{0: <12} {1}", format!("{}:", namespace), namespace_pods);
+    }
+    Ok(())
+}
+
+fn print_taints(node: &Node) {
+    let taints = node
+        .spec
+        .iter()
+        .flat_map(|spec| spec.taints.iter().flatten())
+        .map(|taint| match &taint.value {
+            Some(value) => format!("{}={}:{}", taint.key, value, taint.effect),
+            None => format!("{}:{}", taint.key, taint.effect),
+        })
+        .collect::<Vec<String>>();
+    let taints = if taints.is_empty() {
+        "<none>".to_string()
+    } else {
+        taints.join(", ")
+    };
+    println!("{0: <12} {1}", "taints:", taints);
+}
+
+/// Ready is expected to be True, the pressure/unavailable conditions False
+fn print_conditions(node: &Node) {
+    let conditions = node
+        .status
+        .iter()
+        .flat_map(|status| status.conditions.iter().flatten())
+        .map(|condition| {
+            let text = format!("{}={}", condition.type_, condition.status);
+            let healthy = (condition.type_ == "Ready") == (condition.status == "True");
+            if healthy {
+                text.green().to_string()
+            } else {
+                text.red().to_string()
+            }
+        })
+        .collect::<Vec<String>>();
+    println!("{0: <12} {1}", "conditions:", conditions.join(", "));
+}
+
+fn with_percent(text: String, value: Option<f64>, total: Option<f64>) -> String {
+    match (value, total) {
+        (Some(value), Some(total)) if total > 0.0 => {
+            format!("{} ({:.0}%)", text, value / total * 100.0)
+        }
+        _ => text,
+    }
+}
+
+fn allocated_per_node(pods: &[Pod], namespace: &str) -> HashMap<String, Allocated> {
+    let mut allocated: HashMap<String, Allocated> = HashMap::new();
+    for pod in pods {
+        let Some(node) = pod.spec.as_ref().and_then(|s| s.node_name.as_ref()) else {
+            continue;
+        };
+        let (requests, limits) = pod_requests_and_limits(pod);
+        let entry = allocated.entry(node.to_string()).or_default();
+        entry.requests = entry.requests + requests;
+        entry.limits = entry.limits + limits;
+        entry.pods += 1;
+        if pod.metadata.namespace.as_deref() == Some(namespace) {
+            entry
+                .namespace_pods
+                .push(pod.metadata.name.clone().unwrap_or_default());
+        }
+    }
+    allocated
+        .values_mut()
+        .for_each(|allocated| allocated.namespace_pods.sort());
+    allocated
+}
+
+/// Like the scheduler: the highest of the app containers sum and of each init container
+/// (they run one at a time before the app containers), plus the pod overhead.
+/// The overhead only adds to limits that are set, a missing limit stays unbounded
+fn pod_requests_and_limits(pod: &Pod) -> (CpuMemory, CpuMemory) {
+    let Some(spec) = pod.spec.as_ref() else {
+        return (CpuMemory::default(), CpuMemory::default());
+    };
+    let resources = |container: &Container| {
+        let resources = container.resources.clone().unwrap_or_default();
+        (
+            CpuMemory::from_quantities(&resources.requests.unwrap_or_default()),
+            CpuMemory::from_quantities(&resources.limits.unwrap_or_default()),
+        )
+    };
+    let (requests, limits) = spec.containers.iter().map(resources).fold(
+        (CpuMemory::default(), CpuMemory::default()),
+        |(total_requests, total_limits), (requests, limits)| {
+            (total_requests + requests, total_limits + limits)
+        },
+    );
+    let (requests, limits) = spec.init_containers.iter().flatten().map(resources).fold(
+        (requests, limits),
+        |(total_requests, total_limits), (requests, limits)| {
+            (max(total_requests, requests), max(total_limits, limits))
+        },
+    );
+    let overhead = CpuMemory::from_quantities(&spec.overhead.clone().unwrap_or_default());
+    let bounded_overhead = CpuMemory {
+        cpu: limits.cpu.and(overhead.cpu),
+        memory: limits.memory.and(overhead.memory),
+    };
+    (requests + overhead, limits + bounded_overhead)
+}
+
+fn max(a: CpuMemory, b: CpuMemory) -> CpuMemory {
+    let max = |a: Option<f64>, b: Option<f64>| match (a, b) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, b) => a.or(b),
+    };
+    CpuMemory {
+        cpu: max(a.cpu, b.cpu),
+        memory: max(a.memory, b.memory),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn pod(namespace: &str, name: &str, node: &str, spec: serde_json::Value) -> Pod {
+        let mut spec = spec;
+        spec["nodeName"] = json!(node);
+        serde_json::from_value(json!({
+            "metadata": {"namespace": namespace, "name": name},
+            "spec": spec
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn init_containers_count_when_they_request_more() {
+        let pod = pod(
+            "billing",
+            "billing-0",
+            "node-a",
+            json!({
+                "initContainers": [
+                    {"name": "migrate", "resources": {"requests": {"cpu": "1", "memory": "64Mi"}}}
+                ],
+                "containers": [
+                    {"name": "app", "resources": {
+                        "requests": {"cpu": "250m", "memory": "256Mi"},
+                        "limits": {"memory": "512Mi"}}},
+                    {"name": "sidecar", "resources": {"requests": {"cpu": "50m", "memory": "64Mi"}}}
+                ],
+                "overhead": {"cpu": "10m"}
+            }),
+        );
+
+        let (requests, limits) = pod_requests_and_limits(&pod);
+
+        assert_eq!(requests.cpu, Some(1010.0));
+        assert_eq!(requests.memory, Some(320.0 * 1024.0 * 1024.0));
+        assert_eq!(limits.cpu, None);
+        assert_eq!(limits.memory, Some(512.0 * 1024.0 * 1024.0));
+    }
+
+    #[test]
+    fn pods_are_summed_per_node() {
+        let container = |cpu: &str| json!({"containers": [{"name": "app", "resources": {"requests": {"cpu": cpu}}}]});
+        let pods = vec![
+            pod("billing", "billing-1", "node-a", container("100m")),
+            pod("billing", "billing-0", "node-a", container("200m")),
+            pod("kube-system", "dns", "node-a", container("100m")),
+            pod("billing", "billing-2", "node-b", container("100m")),
+        ];
+
+        let allocated = allocated_per_node(&pods, "billing");
+
+        let node_a = &allocated["node-a"];
+        assert_eq!(node_a.requests.cpu, Some(400.0));
+        assert_eq!(node_a.pods, 3);
+        assert_eq!(node_a.namespace_pods, vec!["billing-0", "billing-1"]);
+        assert_eq!(allocated["node-b"].namespace_pods, vec!["billing-2"]);
+    }
+}
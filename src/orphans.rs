@@ -0,0 +1,557 @@
+use crate::access::can_list_secrets;
+use crate::cli_args::OrphansArgs;
+use crate::errors::MacgyverCmdErrors;
+use crate::{get_k8s_env, Result};
+use colored::Colorize;
+use k8s_openapi::api::apps::v1::{DaemonSet, Deployment, StatefulSet};
+use k8s_openapi::api::batch::v1::{CronJob, Job};
+use k8s_openapi::api::core::v1::{ConfigMap, Namespace, Pod, PodSpec, Secret, ServiceAccount};
+use k8s_openapi::api::networking::v1::Ingress;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::api::ListParams;
+use kube::{api::Api, Client};
+use std::collections::BTreeSet;
+
+/// Created and read by Kubernetes or Helm themselves: they exist for references but are
+/// never reported as unused
+const IGNORED_CONFIGMAPS: &[&str] = &["kube-root-ca.crt"];
+const IGNORED_SECRET_TYPES: &[&str] = &[
+    "kubernetes.io/service-account-token",
+    "helm.sh/release.v1",
+    "bootstrap.kubernetes.io/token",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    ConfigMap,
+    Secret,
+}
+
+impl Kind {
+    fn label(&self) -> &'static str {
+        match self {
+            Kind::ConfigMap => "configmap",
+            Kind::Secret => "secret",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Reference {
+    kind: Kind,
+    name: String,
+    optional: bool,
+    /// e.g. `deployment/billing container app envFrom`
+    from: String,
+    /// From a pod or job created by a controller: it counts as a use, a dangling
+    /// reference is reported on the controller
+    controlled: bool,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Report {
+    unused: Vec<(Kind, String)>,
+    dangling: Vec<Reference>,
+}
+
+#[tokio::main]
+pub async fn process(
+    OrphansArgs {
+        namespaces,
+        all_namespaces,
+    }: OrphansArgs,
+) -> Result<()> {
+    let context = get_k8s_env()?;
+    let client = Client::try_default().await?;
+
+    let namespaces = match all_namespaces {
+        true => Api::<Namespace>::all(client.clone())
+            .list(&ListParams::default())
+            .await?
+            .into_iter()
+            .flat_map(|namespace| namespace.metadata.name)
+            .collect(),
+        false => namespaces,
+    };
+
+    println!("ENVIRONMENT: {context}");
+    let mut errors = 0;
+    for namespace in &namespaces {
+        let report = namespace_report(client.clone(), namespace).await?;
+        errors += report.dangling.iter().filter(|r| !r.optional).count();
+        print_report(namespace, &report);
+    }
+
+    match errors {
+        0 => Ok(()),
+        count => Err(MacgyverCmdErrors::DanglingReferences(count)),
+    }
+}
+
+async fn namespace_report(client: Client, namespace: &str) -> Result<Report> {
+    let lp = ListParams::default();
+    let configmaps: Vec<String> = Api::<ConfigMap>::namespaced(client.clone(), namespace)
+        .list(&lp)
+        .await?
+        .into_iter()
+        .flat_map(|c| c.metadata.name)
+        .collect();
+    // Same access check as `secret`: without it, secrets are neither listed nor checked
+    let secrets_readable = can_list_secrets(client.clone(), namespace).await?;
+    let secret_list = match secrets_readable {
+        true => {
+            Api::<Secret>::namespaced(client.clone(), namespace)
+                .list(&lp)
+                .await?
+                .items
+        }
+        false => vec![],
+    };
+    let secrets: Vec<String> = secret_list
+        .iter()
+        .flat_map(|s| s.metadata.name.clone())
+        .collect();
+    // Still referenced (kube-api-access volumes, legacy ServiceAccount tokens), never reported as unused
+    let ignored: BTreeSet<(Kind, String)> = configmaps
+        .iter()
+        .filter(|name| IGNORED_CONFIGMAPS.contains(&name.as_str()))
+        .map(|name| (Kind::ConfigMap, name.clone()))
+        .chain(
+            secret_list
+                .iter()
+                .filter(|s| IGNORED_SECRET_TYPES.contains(&s.type_.as_deref().unwrap_or_default()))
+                .flat_map(|s| Some((Kind::Secret, s.metadata.name.clone()?))),
+        )
+        .collect();
+
+    let mut references = vec![];
+    for d in Api::<Deployment>::namespaced(client.clone(), namespace)
+        .list(&lp)
+        .await?
+    {
+        let spec = d.spec.and_then(|s| s.template.spec);
+        references.extend(workload_references(
+            "deployment",
+            &d.metadata,
+            spec.as_ref(),
+        ));
+    }
+    for s in Api::<StatefulSet>::namespaced(client.clone(), namespace)
+        .list(&lp)
+        .await?
+    {
+        let spec = s.spec.and_then(|s| s.template.spec);
+        references.extend(workload_references(
+            "statefulset",
+            &s.metadata,
+            spec.as_ref(),
+        ));
+    }
+    for d in Api::<DaemonSet>::namespaced(client.clone(), namespace)
+        .list(&lp)
+        .await?
+    {
+        let spec = d.spec.and_then(|s| s.template.spec);
+        references.extend(workload_references("daemonset", &d.metadata, spec.as_ref()));
+    }
+    for c in Api::<CronJob>::namespaced(client.clone(), namespace)
+        .list(&lp)
+        .await?
+    {
+        let spec = c
+            .spec
+            .and_then(|s| s.job_template.spec)
+            .and_then(|s| s.template.spec);
+        references.extend(workload_references("cronjob", &c.metadata, spec.as_ref()));
+    }
+    for j in Api::<Job>::namespaced(client.clone(), namespace)
+        .list(&lp)
+        .await?
+    {
+        let spec = j.spec.and_then(|s| s.template.spec);
+        references.extend(workload_references("job", &j.metadata, spec.as_ref()));
+    }
+    for p in Api::<Pod>::namespaced(client.clone(), namespace)
+        .list(&lp)
+        .await?
+    {
+        references.extend(workload_references("pod", &p.metadata, p.spec.as_ref()));
+    }
+    for sa in Api::<ServiceAccount>::namespaced(client.clone(), namespace)
+        .list(&lp)
+        .await?
+    {
+        references.extend(service_account_references(&sa));
+    }
+    for ingress in Api::<Ingress>::namespaced(client, namespace)
+        .list(&lp)
+        .await?
+    {
+        let from = format!("ingress/{} tls", ingress.metadata.name.unwrap_or_default());
+        let names = ingress
+            .spec
+            .into_iter()
+            .flat_map(|s| s.tls.unwrap_or_default())
+            .flat_map(|tls| tls.secret_name);
+        references.extend(names.map(|name| Reference {
+            kind: Kind::Secret,
+            name,
+            optional: false,
+            from: from.clone(),
+            controlled: false,
+        }));
+    }
+
+    if !secrets_readable {
+        references.retain(|r| r.kind != Kind::Secret);
+    }
+    Ok(report(&configmaps, &secrets, &ignored, references))
+}
+
+fn workload_references(
+    kind: &str,
+    metadata: &ObjectMeta,
+    spec: Option<&PodSpec>,
+) -> Vec<Reference> {
+    let from = format!("{}/{}", kind, metadata.name.as_deref().unwrap_or_default());
+    let controlled = metadata
+        .owner_references
+        .iter()
+        .flatten()
+        .any(|owner| owner.controller == Some(true));
+    let mut references = spec
+        .map(|spec| pod_spec_references(spec, &from))
+        .unwrap_or_default();
+    references
+        .iter_mut()
+        .for_each(|r| r.controlled = controlled);
+    references
+}
+
+/// Volumes (projected included), env, envFrom and imagePullSecrets of a pod spec
+fn pod_spec_references(spec: &PodSpec, from: &str) -> Vec<Reference> {
+    let mut references = vec![];
+    let mut add = |kind: Kind, name: &Option<String>, optional: Option<bool>, what: String| {
+        if let Some(name) = name {
+            references.push(Reference {
+                kind,
+                name: name.clone(),
+                optional: optional.unwrap_or(false),
+                from: format!("{} {}", from, what),
+                controlled: false,
+            });
+        }
+    };
+
+    for volume in spec.volumes.iter().flatten() {
+        let what = format!("volume {}", volume.name);
+        if let Some(c) = &volume.config_map {
+            add(Kind::ConfigMap, &c.name, c.optional, what.clone());
+        }
+        if let Some(s) = &volume.secret {
+            add(Kind::Secret, &s.secret_name, s.optional, what.clone());
+        }
+        let sources = volume
+            .projected
+            .iter()
+            .flat_map(|p| p.sources.iter().flatten());
+        for source in sources {
+            if let Some(c) = &source.config_map {
+                add(Kind::ConfigMap, &c.name, c.optional, what.clone());
+            }
+            if let Some(s) = &source.secret {
+                add(Kind::Secret, &s.name, s.optional, what.clone());
+            }
+        }
+    }
+
+    let containers = spec
+        .init_containers
+        .iter()
+        .flatten()
+        .chain(&spec.containers);
+    for container in containers {
+        for env_from in container.env_from.iter().flatten() {
+            let what = format!("container {} envFrom", container.name);
+            if let Some(c) = &env_from.config_map_ref {
+                add(Kind::ConfigMap, &c.name, c.optional, what.clone());
+            }
+            if let Some(s) = &env_from.secret_ref {
+                add(Kind::Secret, &s.name, s.optional, what);
+            }
+        }
+        for env in container.env.iter().flatten() {
+            let what = format!("container {} env {}", container.name, env.name);
+            let value_from = env.value_from.as_ref();
+            if let Some(c) = value_from.and_then(|v| v.config_map_key_ref.as_ref()) {
+                add(Kind::ConfigMap, &c.name, c.optional, what.clone());
+            }
+            if let Some(s) = value_from.and_then(|v| v.secret_key_ref.as_ref()) {
+                add(Kind::Secret, &s.name, s.optional, what);
+            }
+        }
+    }
+
+    for secret in spec.image_pull_secrets.iter().flatten() {
+        add(
+            Kind::Secret,
+            &secret.name,
+            None,
+            "imagePullSecrets".to_string(),
+        );
+    }
+    references
+}
+
+fn service_account_references(sa: &ServiceAccount) -> Vec<Reference> {
+    let from = format!(
+        "serviceaccount/{}",
+        sa.metadata.name.as_deref().unwrap_or_default()
+    );
+    // Legacy token secrets, Kubernetes 1.24+ cleans them up: a missing one is only informational
+    let secrets = sa
+        .secrets
+        .iter()
+        .flatten()
+        .flat_map(|s| s.name.clone())
+        .map(|name| (name, "secrets (legacy token)", true));
+    let image_pull_secrets = sa
+        .image_pull_secrets
+        .iter()
+        .flatten()
+        .flat_map(|s| s.name.clone())
+        .map(|name| (name, "imagePullSecrets", false));
+    secrets
+        .chain(image_pull_secrets)
+        .map(|(name, what, optional)| Reference {
+            kind: Kind::Secret,
+            name,
+            optional,
+            from: format!("{} {}", from, what),
+            controlled: false,
+        })
+        .collect()
+}
+
+/// Objects nobody references, and references (outside controlled objects) to objects that do not exist
+fn report(
+    configmaps: &[String],
+    secrets: &[String],
+    ignored: &BTreeSet<(Kind, String)>,
+    references: Vec<Reference>,
+) -> Report {
+    let exists = |kind: Kind, name: &str| match kind {
+        Kind::ConfigMap => configmaps.iter().any(|c| c == name),
+        Kind::Secret => secrets.iter().any(|s| s == name),
+    };
+    let used: BTreeSet<(Kind, &str)> = references
+        .iter()
+        .map(|r| (r.kind, r.name.as_str()))
+        .collect();
+
+    let unused = configmaps
+        .iter()
+        .map(|name| (Kind::ConfigMap, name))
+        .chain(secrets.iter().map(|name| (Kind::Secret, name)))
+        .filter(|(kind, name)| !used.contains(&(*kind, name.as_str())))
+        .filter(|(kind, name)| !ignored.contains(&(*kind, name.to_string())))
+        .map(|(kind, name)| (kind, name.clone()))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let mut dangling: Vec<Reference> = references
+        .iter()
+        .filter(|r| !r.controlled && !exists(r.kind, &r.name))
+        .cloned()
+        .collect();
+    dangling.sort_by(|a, b| (a.kind, &a.name, &a.from).cmp(&(b.kind, &b.name, &b.from)));
+    dangling.dedup();
+    Report { unused, dangling }
+}
+
+fn print_report(namespace: &str, report: &Report) {
+    println!("{}", namespace.bold().white());
+    if report.unused.is_empty() && report.dangling.is_empty() {
+        println!("      {}", "no orphans".green());
+    }
+    for (kind, name) in &report.unused {
+        println!(
+            "      {0:_<60} {1}",
+            format!("{}/{}", kind.label(), name),
+            "unused".yellow()
+        );
+    }
+    for reference in &report.dangling {
+        let status = match reference.optional {
+            true => "missing (optional)".yellow(),
+            false => "missing".red(),
+        };
+        println!(
+            "      {0:_<60} {1: <20} {2}",
+            format!("{}/{}", reference.kind.label(), reference.name),
+            status,
+            reference.from.dimmed()
+        );
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn spec() -> PodSpec {
+        serde_json::from_value(json!({
+            "containers": [{
+                "name": "app",
+                "envFrom": [{"configMapRef": {"name": "app"}}],
+                "env": [
+                    {"name": "TOKEN", "valueFrom": {"secretKeyRef": {"name": "api", "key": "token"}}},
+                    {"name": "FLAGS", "valueFrom": {"configMapKeyRef": {"name": "flags", "key": "all", "optional": true}}}
+                ]
+            }],
+            "initContainers": [{"name": "migrate", "envFrom": [{"secretRef": {"name": "db"}}]}],
+            "volumes": [
+                {"name": "config", "configMap": {"name": "app-files"}},
+                {"name": "tls", "secret": {"secretName": "tls"}},
+                {"name": "all", "projected": {"sources": [
+                    {"configMap": {"name": "ca"}},
+                    {"secret": {"name": "client-cert"}}
+                ]}}
+            ],
+            "imagePullSecrets": [{"name": "registry"}]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn every_kind_of_pod_spec_reference() {
+        let references: Vec<String> = pod_spec_references(&spec(), "deployment/billing")
+            .iter()
+            .map(|r| format!("{}/{} {} {}", r.kind.label(), r.name, r.optional, r.from))
+            .collect();
+
+        assert_eq!(
+            references,
+            vec![
+                "configmap/app-files false deployment/billing volume config",
+                "secret/tls false deployment/billing volume tls",
+                "configmap/ca false deployment/billing volume all",
+                "secret/client-cert false deployment/billing volume all",
+                "secret/db false deployment/billing container migrate envFrom",
+                "configmap/app false deployment/billing container app envFrom",
+                "secret/api false deployment/billing container app env TOKEN",
+                "configmap/flags true deployment/billing container app env FLAGS",
+                "secret/registry false deployment/billing imagePullSecrets",
+            ]
+        );
+    }
+
+    #[test]
+    fn unused_and_dangling_objects() {
+        let mut references = pod_spec_references(&spec(), "deployment/billing");
+        // Same spec on a pod of the deployment: a use, not reported twice
+        let mut pod = pod_spec_references(&spec(), "pod/billing-7d9f");
+        pod.iter_mut().for_each(|r| r.controlled = true);
+        references.extend(pod);
+        let configmaps = vec![
+            "app".to_string(),
+            "app-files".to_string(),
+            "ca".to_string(),
+            "old".to_string(),
+        ];
+        let secrets = vec![
+            "api".to_string(),
+            "db".to_string(),
+            "tls".to_string(),
+            "client-cert".to_string(),
+            "legacy".to_string(),
+        ];
+
+        let report = report(&configmaps, &secrets, &BTreeSet::new(), references);
+
+        assert_eq!(
+            report.unused,
+            vec![
+                (Kind::ConfigMap, "old".to_string()),
+                (Kind::Secret, "legacy".to_string())
+            ]
+        );
+        let dangling: Vec<(&str, bool, &str)> = report
+            .dangling
+            .iter()
+            .map(|r| (r.name.as_str(), r.optional, r.from.as_str()))
+            .collect();
+        assert_eq!(
+            dangling,
+            vec![
+                ("flags", true, "deployment/billing container app env FLAGS"),
+                ("registry", false, "deployment/billing imagePullSecrets"),
+            ]
+        );
+    }
+
+    #[test]
+    fn ignored_objects_still_exist_for_references() {
+        let pod: PodSpec = serde_json::from_value(json!({
+            "containers": [{"name": "app"}],
+            "volumes": [{"name": "kube-api-access-x2k9z", "projected": {"sources": [
+                {"serviceAccountToken": {"path": "token"}},
+                {"configMap": {"name": "kube-root-ca.crt"}}
+            ]}}]
+        }))
+        .unwrap();
+        let sa: ServiceAccount = serde_json::from_value(json!({
+            "metadata": {"name": "default"},
+            "secrets": [{"name": "default-token-7xk2p"}]
+        }))
+        .unwrap();
+        let mut references = pod_spec_references(&pod, "pod/debug");
+        references.extend(service_account_references(&sa));
+        let configmaps = vec!["kube-root-ca.crt".to_string()];
+        let secrets = vec![
+            "default-token-7xk2p".to_string(),
+            "sh.helm.release.v1.billing.v1".to_string(),
+        ];
+        let ignored = BTreeSet::from([
+            (Kind::ConfigMap, "kube-root-ca.crt".to_string()),
+            (Kind::Secret, "default-token-7xk2p".to_string()),
+            (Kind::Secret, "sh.helm.release.v1.billing.v1".to_string()),
+        ]);
+
+        let report = report(&configmaps, &secrets, &ignored, references);
+
+        assert_eq!(report.unused, vec![]);
+        assert_eq!(report.dangling, vec![]);
+    }
+
+    #[test]
+    fn cleaned_up_service_account_tokens_are_not_errors() {
+        let sa: ServiceAccount = serde_json::from_value(json!({
+            "metadata": {"name": "default"},
+            "secrets": [{"name": "default-token-7xk2p"}],
+            "imagePullSecrets": [{"name": "registry"}]
+        }))
+        .unwrap();
+
+        let report = report(&[], &[], &BTreeSet::new(), service_account_references(&sa));
+
+        let dangling: Vec<(&str, bool, &str)> = report
+            .dangling
+            .iter()
+            .map(|r| (r.name.as_str(), r.optional, r.from.as_str()))
+            .collect();
+        assert_eq!(
+            dangling,
+            vec![
+                (
+                    "default-token-7xk2p",
+                    true,
+                    "serviceaccount/default secrets (legacy token)"
+                ),
+                ("registry", false, "serviceaccount/default imagePullSecrets"),
+            ]
+        );
+    }
+}
@@ -96,7 +96,7 @@ pub async fn process(
         .list(&ListParams::default())
         .await?
         .into_iter()
-        .map(|p| collect_info(p))
+        .map(collect_info)
         .collect();
 
     let mut results: Vec<Option<PrintArguments>> = vec![Some(headers)];
@@ -109,7 +109,7 @@ pub async fn process(
 
 fn print_result(context: &str, info: &[Option<PrintArguments>], with_pod: bool) {
     println!("ENVIRONMENT: {context}");
-    info.into_iter().flatten().for_each(|i| {
+    info.iter().flatten().for_each(|i| {
         if with_pod {
             println!(
                 "{0: <105} {1: <25} {2: <18} {3: <30}",
@@ -132,7 +132,7 @@ fn collect_info(p: Pod) -> Option<PrintArguments> {
     let containers = &spec.containers;
 
     let images: Vec<PodInfo> = containers
-        .into_iter()
+        .iter()
         .flat_map(|x| x.image.as_ref().map(|image| PodInfo { image }))
         .filter(|x| !x.image.contains("istio"))
         .collect();
@@ -140,9 +140,8 @@ fn collect_info(p: Pod) -> Option<PrintArguments> {
     for image in images {
         if let Some(container_status) = &status.container_statuses {
             let maybe_image_status = container_status
-                .into_iter()
-                .filter(|status| image.image == status.image)
-                .last();
+                .iter()
+                .rfind(|status| image.image == status.image);
 
             if let Some(image_status) = maybe_image_status {
                 let status_ops = ContainerStatusOps::new(image_status);
@@ -156,5 +155,5 @@ fn collect_info(p: Pod) -> Option<PrintArguments> {
             }
         }
     }
-    return None;
+    None
 }
@@ -0,0 +1,284 @@
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use std::collections::BTreeMap;
+use std::ops::{Add, Mul};
+use std::str::FromStr;
+
+/// CPU quantity (`500m`, `0.5`, `1`) in millicores
+pub fn cpu_millis(quantity: &Quantity) -> Option<f64> {
+    parse(&quantity.0).map(|cores| cores * 1000.0)
+}
+
+/// Memory quantity (`512Mi`, `1G`, `1048576`) in bytes
+pub fn memory_bytes(quantity: &Quantity) -> Option<f64> {
+    parse(&quantity.0)
+}
+
+/// Parses a Kubernetes quantity into its base unit: a number followed by a binary SI
+/// suffix (`Ki` .. `Ei`), a decimal SI suffix (`n` .. `E`) or a decimal exponent (`e3`, `E-2`)
+pub fn parse(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let split = value
+        .char_indices()
+        .find(|(index, c)| !(c.is_ascii_digit() || *c == '.' || (*index == 0 && "+-".contains(*c))))
+        .map(|(index, _)| index)
+        .unwrap_or(value.len());
+    let (number, suffix) = value.split_at(split);
+    let number = number.parse::<f64>().ok()?;
+
+    let multiplier = match suffix {
+        "" => 1.0,
+        "n" => 1e-9,
+        "u" => 1e-6,
+        "m" => 1e-3,
+        "k" => 1e3,
+        "M" => 1e6,
+        "G" => 1e9,
+        "T" => 1e12,
+        "P" => 1e15,
+        "E" => 1e18,
+        "Ki" => 2f64.powi(10),
+        "Mi" => 2f64.powi(20),
+        "Gi" => 2f64.powi(30),
+        "Ti" => 2f64.powi(40),
+        "Pi" => 2f64.powi(50),
+        "Ei" => 2f64.powi(60),
+        exponent => {
+            let exponent = exponent
+                .strip_prefix('e')
+                .or_else(|| exponent.strip_prefix('E'))?;
+            10f64.powi(exponent.parse::<i32>().ok()?)
+        }
+    };
+    Some(number * multiplier)
+}
+
+/// CPU (millicores) and memory (bytes) of a requests/limits/usage map
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CpuMemory {
+    pub cpu: Option<f64>,
+    pub memory: Option<f64>,
+}
+
+impl CpuMemory {
+    pub fn from_quantities(quantities: &BTreeMap<String, Quantity>) -> Self {
+        Self {
+            cpu: quantities.get("cpu").and_then(cpu_millis),
+            memory: quantities.get("memory").and_then(memory_bytes),
+        }
+    }
+}
+
+/// Sum of the values that are set, `None` only when both sides miss the value
+impl Add for CpuMemory {
+    type Output = CpuMemory;
+
+    fn add(self, other: CpuMemory) -> CpuMemory {
+        let add = |a: Option<f64>, b: Option<f64>| match (a, b) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        };
+        CpuMemory {
+            cpu: add(self.cpu, other.cpu),
+            memory: add(self.memory, other.memory),
+        }
+    }
+}
+
+impl Mul<f64> for CpuMemory {
+    type Output = CpuMemory;
+
+    fn mul(self, factor: f64) -> CpuMemory {
+        CpuMemory {
+            cpu: self.cpu.map(|cpu| cpu * factor),
+            memory: self.memory.map(|memory| memory * factor),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CpuUnit {
+    Millicores,
+    Cores,
+}
+
+/// Units used to print quantities, written `cpu:memory` e.g. `m:Mi` or `cores:Gi`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Units {
+    pub cpu: CpuUnit,
+    pub memory_suffix: &'static str,
+    pub memory_bytes: f64,
+}
+
+impl Default for Units {
+    fn default() -> Self {
+        Self {
+            cpu: CpuUnit::Millicores,
+            memory_suffix: "Mi",
+            memory_bytes: 2f64.powi(20),
+        }
+    }
+}
+
+impl FromStr for Units {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid units {}. Units have syntax cpu:memory, cpu is m or cores, memory is one of bytes, k, M, G, T, Ki, Mi, Gi, Ti",
+                value
+            )
+        };
+        let (cpu, memory) = value.split_once(':').ok_or_else(invalid)?;
+        let cpu = match cpu {
+            "m" => CpuUnit::Millicores,
+            "cores" => CpuUnit::Cores,
+            _ => return Err(invalid()),
+        };
+        let (memory_suffix, memory_bytes) = match memory {
+            "bytes" => ("", 1.0),
+            "k" => ("k", 1e3),
+            "M" => ("M", 1e6),
+            "G" => ("G", 1e9),
+            "T" => ("T", 1e12),
+            "Ki" => ("Ki", 2f64.powi(10)),
+            "Mi" => ("Mi", 2f64.powi(20)),
+            "Gi" => ("Gi", 2f64.powi(30)),
+            "Ti" => ("Ti", 2f64.powi(40)),
+            _ => return Err(invalid()),
+        };
+        Ok(Units {
+            cpu,
+            memory_suffix,
+            memory_bytes,
+        })
+    }
+}
+
+impl Units {
+    pub fn cpu(&self, millis: Option<f64>) -> String {
+        match (millis, self.cpu) {
+            (Some(millis), CpuUnit::Millicores) => format!("{}m", number(millis)),
+            (Some(millis), CpuUnit::Cores) => number(millis / 1000.0),
+            (None, _) => "N/A".to_string(),
+        }
+    }
+
+    pub fn memory(&self, bytes: Option<f64>) -> String {
+        match bytes {
+            Some(bytes) => format!(
+                "{}{}",
+                number(bytes / self.memory_bytes),
+                self.memory_suffix
+            ),
+            None => "N/A".to_string(),
+        }
+    }
+}
+
+fn number(value: f64) -> String {
+    if (value - value.round()).abs() < 0.005 {
+        format!("{:.0}", value)
+    } else {
+        format!("{:.2}", value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_and_decimal_numbers() {
+        assert_eq!(parse("1"), Some(1.0));
+        assert_eq!(parse("0.5"), Some(0.5));
+        assert_eq!(parse(".5"), Some(0.5));
+        assert_eq!(parse("+2"), Some(2.0));
+        assert_eq!(parse("-2"), Some(-2.0));
+    }
+
+    #[test]
+    fn decimal_si_suffixes() {
+        assert_eq!(parse("500m"), Some(0.5));
+        assert_eq!(parse("250000u"), Some(0.25));
+        assert_eq!(parse("100000000n"), Some(0.1));
+        assert_eq!(parse("1k"), Some(1e3));
+        assert_eq!(parse("1G"), Some(1e9));
+        assert_eq!(parse("2T"), Some(2e12));
+        assert_eq!(parse("1P"), Some(1e15));
+        assert_eq!(parse("1E"), Some(1e18));
+    }
+
+    #[test]
+    fn binary_si_suffixes() {
+        assert_eq!(parse("1Ki"), Some(1024.0));
+        assert_eq!(parse("512Mi"), Some(512.0 * 1024.0 * 1024.0));
+        assert_eq!(parse("1.5Gi"), Some(1.5 * 1024.0 * 1024.0 * 1024.0));
+        assert_eq!(parse("1Ti"), Some(2f64.powi(40)));
+        assert_eq!(parse("1Pi"), Some(2f64.powi(50)));
+        assert_eq!(parse("1Ei"), Some(2f64.powi(60)));
+    }
+
+    #[test]
+    fn decimal_exponents() {
+        assert_eq!(parse("1e3"), Some(1e3));
+        assert_eq!(parse("5E2"), Some(500.0));
+        assert_eq!(parse("1e-3"), Some(1e-3));
+    }
+
+    #[test]
+    fn invalid_quantities() {
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("Mi"), None);
+        assert_eq!(parse("1mi"), None);
+        assert_eq!(parse("1KiB"), None);
+        assert_eq!(parse("1-2"), None);
+    }
+
+    #[test]
+    fn cpu_and_memory_are_normalized() {
+        let q = |value: &str| Quantity(value.to_string());
+        assert_eq!(cpu_millis(&q("500m")), Some(500.0));
+        assert_eq!(cpu_millis(&q("0.5")), Some(500.0));
+        assert_eq!(cpu_millis(&q("1")), Some(1000.0));
+        assert_eq!(memory_bytes(&q("1G")), Some(1e9));
+
+        let units = Units::default();
+        assert_eq!(units.cpu(cpu_millis(&q("0.5"))), "500m");
+        assert_eq!(units.memory(memory_bytes(&q("512Mi"))), "512Mi");
+        assert_eq!(units.memory(memory_bytes(&q("1G"))), "953.67Mi");
+        assert_eq!(units.memory(None), "N/A");
+    }
+
+    #[test]
+    fn units_from_str() {
+        let units: Units = "cores:Gi".parse().unwrap();
+        assert_eq!(units.cpu(Some(1500.0)), "1.50");
+        assert_eq!(units.memory(Some(2f64.powi(31))), "2Gi");
+        assert!("m".parse::<Units>().is_err());
+        assert!("mcores:Mi".parse::<Units>().is_err());
+    }
+
+    #[test]
+    fn sum_keeps_values_that_are_set() {
+        let a = CpuMemory {
+            cpu: Some(100.0),
+            memory: None,
+        };
+        let b = CpuMemory {
+            cpu: Some(50.0),
+            memory: Some(10.0),
+        };
+        assert_eq!(
+            a + b,
+            CpuMemory {
+                cpu: Some(150.0),
+                memory: Some(10.0)
+            }
+        );
+        assert_eq!(
+            CpuMemory::default() + CpuMemory::default(),
+            CpuMemory::default()
+        );
+    }
+}
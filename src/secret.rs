@@ -1,9 +1,17 @@
+mod render;
+
+use crate::access::{check_secret_access, confirm_context, AuditLog};
 use crate::cli_args::NamespaceWithEncodedSecretArgs;
-use crate::{check_secret, get_k8s_env, Result};
+use crate::diff::{self, Side};
+use crate::export::{self, Exported};
+use crate::{get_k8s_env, Result};
 use base64::{engine, Engine as _};
+use chrono::{DateTime, Utc};
 use colored::Colorize;
 use k8s_openapi::api::core::v1::Secret;
 use kube::{api::ListParams, Api, Client};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
 use std::{fmt::Display, str};
 
 #[tokio::main]
@@ -11,37 +19,166 @@ pub async fn process(
     NamespaceWithEncodedSecretArgs {
         namespace,
         with_encoded,
+        types,
+        reveal,
+        reveal_all,
+        diff,
+        export,
+        names,
     }: NamespaceWithEncodedSecretArgs,
 ) -> Result<()> {
-    check_secret()?;
     let context = get_k8s_env()?;
     let client = Client::try_default().await?;
+    check_secret_access(client.clone(), namespace.as_str()).await?;
+    let selected = |s: &Secret| {
+        types.iter().any(|t| t == secret_type(s))
+            && (names.is_empty() || s.metadata.name.as_ref().is_some_and(|n| names.contains(n)))
+    };
+
+    if let Some((target, label, target_namespace)) = diff::target(&diff, &namespace).await? {
+        check_secret_access(target.clone(), &target_namespace).await?;
+        // A salt per run: the hashes can not be compared with other runs or rainbow tables
+        let salt: [u8; 16] = rand::random();
+        let left = Side {
+            label: format!("{}/{}", context, namespace),
+            objects: salted_hashes(client, &namespace, &selected, &salt).await?,
+        };
+        let right = Side {
+            label,
+            objects: salted_hashes(target, &target_namespace, &selected, &salt).await?,
+        };
+        diff::print(&left, &right);
+        return Ok(());
+    }
+
     let api: Api<Secret> = Api::namespaced(client, namespace.as_str());
 
+    if let Some(format) = export.export {
+        confirm_context()?;
+        let audit = AuditLog::open()?;
+        let mut exported = vec![];
+        for mut object in api
+            .list(&ListParams::default())
+            .await?
+            .into_iter()
+            .filter(selected)
+        {
+            let values: BTreeMap<String, Vec<u8>> = object
+                .data
+                .iter()
+                .flatten()
+                .map(|(key, value)| (key.clone(), value.0.clone()))
+                .collect();
+            let keys: Vec<&str> = values.keys().map(|key| key.as_str()).collect();
+            audit.record(
+                namespace.as_str(),
+                object.metadata.name.as_deref().unwrap_or_default(),
+                &keys,
+            )?;
+            export::clean(&mut object);
+            exported.push(Exported { object, values });
+        }
+        return export::write(format, export.out_dir.as_deref(), exported);
+    }
+
     println!("ENVIRONMENT: {context}");
 
-    api.list(&ListParams::default())
+    let reveal = Reveal {
+        keys: reveal,
+        all: reveal_all,
+    };
+    let audit = if reveal.any() {
+        confirm_context()?;
+        Some(AuditLog::open()?)
+    } else {
+        None
+    };
+    let now = Utc::now();
+    // A key per run: the fingerprints of low entropy values can not be brute forced offline
+    let fingerprint_key: [u8; 32] = rand::random();
+    let secrets = api
+        .list(&ListParams::default())
         .await?
         .into_iter()
-        .filter(|x| x.type_.as_ref().unwrap() == "Opaque")
-        .for_each(|s| {
-            print_secret(s, with_encoded);
-        });
+        .filter(selected);
+    for s in secrets {
+        if let (Some(audit), Some(name), Some(data)) = (&audit, &s.metadata.name, &s.data) {
+            let revealed: Vec<&str> = data
+                .keys()
+                .map(|key| key.as_str())
+                .filter(|key| reveal.contains(key))
+                .collect();
+            audit.record(namespace.as_str(), name, &revealed)?;
+        }
+        print_secret(s, with_encoded, &reveal, &fingerprint_key, now);
+    }
     Ok(())
 }
 
-fn print_secret(s: Secret, with_encoded: bool) -> Option<()> {
+async fn salted_hashes(
+    client: Client,
+    namespace: &str,
+    selected: impl Fn(&Secret) -> bool,
+    salt: &[u8],
+) -> Result<BTreeMap<String, BTreeMap<String, Vec<u8>>>> {
+    let api: Api<Secret> = Api::namespaced(client, namespace);
+    Ok(api
+        .list(&ListParams::default())
+        .await?
+        .into_iter()
+        .filter(|s| selected(s))
+        .flat_map(|s| {
+            let hashes = s
+                .data
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(key, value)| (key, salted_hash(salt, &value.0)))
+                .collect();
+            Some((s.metadata.name?, hashes))
+        })
+        .collect())
+}
+
+fn salted_hash(salt: &[u8], value: &[u8]) -> Vec<u8> {
+    Sha256::new()
+        .chain_update(salt)
+        .chain_update(value)
+        .finalize()
+        .to_vec()
+}
+
+/// The API server defaults the type to Opaque, older objects may still miss it
+fn secret_type(secret: &Secret) -> &str {
+    secret.type_.as_deref().unwrap_or("Opaque")
+}
+
+fn print_secret(
+    s: Secret,
+    with_encoded: bool,
+    reveal: &Reveal,
+    fingerprint_key: &[u8],
+    now: DateTime<Utc>,
+) -> Option<()> {
+    let secret_type = secret_type(&s).to_string();
     if let (Some(name), Some(data)) = (s.metadata.name, s.data) {
-        println!("{}", name.bold().white());
+        println!("{} {}", name.bold().white(), secret_type.dimmed());
         data.iter().for_each(|(key, value)| {
-            let value_byte = value.0.as_ref();
-            let base64 = engine::general_purpose::STANDARD.encode(&value_byte);
-            let decoded = std::str::from_utf8(&value_byte).unwrap_or("Error decoding");
+            let value_byte: &[u8] = value.0.as_ref();
+            let revealed = reveal.contains(key);
+            if !revealed {
+                if let Some(lines) = render::render(&secret_type, key, value_byte, now) {
+                    lines
+                        .iter()
+                        .for_each(|(label, value)| println!("      {0:_<50} {1}", label, value));
+                    return;
+                }
+            }
             let secret = Decoded {
                 key,
-                decoded,
-                base64: base64.as_str(),
+                value: value_byte,
+                revealed,
                 with_encoded,
+                fingerprint_key,
             };
             println!("{}", secret);
         })
@@ -51,23 +188,178 @@ fn print_secret(s: Secret, with_encoded: bool) -> Option<()> {
     None
 }
 
+/// Keys printed in cleartext, every other value is masked
+struct Reveal {
+    keys: Vec<String>,
+    all: bool,
+}
+
+impl Reveal {
+    fn any(&self) -> bool {
+        self.all || !self.keys.is_empty()
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        self.all || self.keys.iter().any(|k| k == key)
+    }
+}
+
+/// First 8 hex digits of the HMAC-SHA256: tells values apart within a run without showing them
+fn fingerprint(key: &[u8], value: &[u8]) -> String {
+    hmac_sha256(key, value)
+        .iter()
+        .take(4)
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// RFC 2104 HMAC, keys longer than the sha256 block are hashed first
+fn hmac_sha256(key: &[u8], value: &[u8]) -> Vec<u8> {
+    const BLOCK_SIZE: usize = 64;
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |byte: u8| block.iter().map(|b| b ^ byte).collect::<Vec<u8>>();
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(value)
+        .finalize();
+    Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .to_vec()
+}
+
 struct Decoded<'a> {
     key: &'a str,
-    decoded: &'a str,
-    base64: &'a str,
+    value: &'a [u8],
+    revealed: bool,
     with_encoded: bool,
+    fingerprint_key: &'a [u8],
 }
 
 impl<'a> Display for Decoded<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if self.with_encoded {
+        let length = self.value.len();
+        let decoded = match (self.revealed, str::from_utf8(self.value)) {
+            (true, Ok(decoded)) => decoded.to_string(),
+            (true, Err(_)) => format!(
+                "<binary, {} bytes> {}",
+                length,
+                engine::general_purpose::STANDARD.encode(self.value)
+            ),
+            (false, Ok(_)) => format!(
+                "<masked, {} bytes, hmac:{}>",
+                length,
+                fingerprint(self.fingerprint_key, self.value)
+            ),
+            (false, Err(_)) => format!(
+                "<binary, {} bytes, hmac:{}>",
+                length,
+                fingerprint(self.fingerprint_key, self.value)
+            ),
+        };
+        if self.with_encoded && self.revealed {
             write!(
                 f,
                 "      {0:_<50} {1:_<70} {2}",
-                self.key, self.decoded, self.base64
+                self.key,
+                decoded,
+                engine::general_purpose::STANDARD.encode(self.value)
             )
         } else {
-            write!(f, "      {0:_<50} {1}", self.key, self.decoded)
+            write!(f, "      {0:_<50} {1}", self.key, decoded)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoded(value: &[u8], revealed: bool, with_encoded: bool) -> String {
+        Decoded {
+            key: "password",
+            value,
+            revealed,
+            with_encoded,
+            fingerprint_key: b"key",
         }
+        .to_string()
+    }
+
+    #[test]
+    fn values_are_masked_unless_revealed() {
+        let masked = decoded(b"hunter2", false, true);
+        assert!(masked.ends_with(&format!(
+            "<masked, 7 bytes, hmac:{}>",
+            fingerprint(b"key", b"hunter2")
+        )));
+        assert!(!masked.contains("hunter2"));
+        assert!(!masked.contains("aHVudGVyMg=="));
+
+        assert!(decoded(b"hunter2", true, false).ends_with(" hunter2"));
+        assert!(decoded(b"hunter2", true, true).ends_with(" aHVudGVyMg=="));
+    }
+
+    #[test]
+    fn binary_values_are_marked() {
+        let binary = [0xff, 0xfe, 0x00];
+        assert!(decoded(&binary, false, false).ends_with(&format!(
+            "<binary, 3 bytes, hmac:{}>",
+            fingerprint(b"key", &binary)
+        )));
+        assert!(decoded(&binary, true, false).ends_with("<binary, 3 bytes> //4A"));
+    }
+
+    #[test]
+    fn salted_hashes_only_match_with_the_same_salt() {
+        let salt = [1u8; 16];
+        assert_eq!(
+            salted_hash(&salt, b"postgres://db"),
+            salted_hash(&salt, b"postgres://db")
+        );
+        assert_ne!(
+            salted_hash(&salt, b"postgres://db"),
+            salted_hash(&salt, b"postgres://other")
+        );
+        assert_ne!(
+            salted_hash(&salt, b"postgres://db"),
+            salted_hash(&[2u8; 16], b"postgres://db")
+        );
+        assert_ne!(
+            salted_hash(&salt, b"postgres://db"),
+            Sha256::digest(b"postgres://db").to_vec()
+        );
+    }
+
+    #[test]
+    fn fingerprint_is_a_short_keyed_hmac() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            hmac_sha256(b"Jefe", b"what do ya want for nothing?"),
+            [
+                0x5b, 0xdc, 0xc1, 0x46, 0xbf, 0x60, 0x75, 0x4e, 0x6a, 0x04, 0x24, 0x26, 0x08, 0x95,
+                0x75, 0xc7, 0x5a, 0x00, 0x3f, 0x08, 0x9d, 0x27, 0x39, 0x83, 0x9d, 0xec, 0x58, 0xb9,
+                0x64, 0xec, 0x38, 0x43
+            ]
+        );
+        assert_eq!(
+            fingerprint(b"Jefe", b"what do ya want for nothing?"),
+            "5bdcc146"
+        );
+        assert_ne!(fingerprint(b"key", b"a"), fingerprint(b"key", b"b"));
+        assert_ne!(fingerprint(b"run 1", b"a"), fingerprint(b"run 2", b"a"));
+
+        let reveal = Reveal {
+            keys: vec!["username".to_string()],
+            all: false,
+        };
+        assert!(reveal.contains("username"));
+        assert!(!reveal.contains("password"));
     }
 }